@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+/// Identifies a search: its query string parameters, sorted and trimmed, and the languages its
+/// translations were searched in.
+#[derive(PartialEq, Eq, Hash, Clone)]
+pub struct CacheKey {
+    params: Vec<(String, String)>,
+    languages: Vec<String>,
+}
+
+impl CacheKey {
+    /// The key of a search with these query string pairs. `cache` and `fields` are left out,
+    /// since they don't change which cards match.
+    pub fn new(pairs: &[(String, String)], languages: Vec<String>) -> Self {
+        let mut params: Vec<(String, String)> = pairs
+            .iter()
+            .filter(|(key, value)| {
+                !matches!(key.as_str(), "cache" | "fields") && !value.trim().is_empty()
+            })
+            .map(|(key, value)| (key.clone(), value.trim().to_string()))
+            .collect();
+        params.sort_unstable();
+        Self { params, languages }
+    }
+}
+
+/// What's remembered of a search.
+#[derive(Clone)]
+pub struct CachedSearch {
+    /// Ids of the matching cards, in the order they're sent.
+    pub ids: Vec<String>,
+    /// Whether typos were allowed to find them.
+    pub approximate: bool,
+}
+
+/// How often searches were answered from the cache.
+#[derive(Serialize, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+    pub capacity: usize,
+}
+
+/// The results of the most recently used searches. Only card ids are kept, and each entry
+/// remembers the catalog version it was made with, so a reload never brings back stale cards.
+pub struct ResultCache {
+    capacity: usize,
+    /// Every entry with the catalog version it belongs to and when it was last used.
+    entries: HashMap<CacheKey, (u64, u64, CachedSearch)>,
+    /// Goes up by one with every use, to tell which entry was used longest ago.
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl ResultCache {
+    /// A cache of at most `capacity` searches. With a capacity of 0 nothing is cached.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &CacheKey, version: u64) -> Option<CachedSearch> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some((entry_version, used, search)) if *entry_version == version => {
+                *used = self.clock;
+                self.hits += 1;
+                Some(search.clone())
+            }
+            _ => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Remembers a search, forgetting the one used longest ago if the cache is full.
+    pub fn insert(&mut self, key: CacheKey, version: u64, search: CachedSearch) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() >= self.capacity && !self.entries.contains_key(&key) {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, used, _))| *used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.clock += 1;
+        self.entries.insert(key, (version, self.clock, search));
+    }
+
+    /// Forgets every search, for when the catalog is replaced.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits,
+            misses: self.misses,
+            entries: self.entries.len(),
+            capacity: self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(query: &str) -> CacheKey {
+        CacheKey::new(&[("query".to_string(), query.to_string())], vec![])
+    }
+
+    fn cached(id: &str) -> CachedSearch {
+        CachedSearch {
+            ids: vec![id.to_string()],
+            approximate: false,
+        }
+    }
+
+    #[test]
+    fn forgets_the_search_used_longest_ago() {
+        let mut cache = ResultCache::new(2);
+        cache.insert(key("a"), 1, cached("a"));
+        cache.insert(key("b"), 1, cached("b"));
+        assert!(cache.get(&key("a"), 1).is_some());
+        cache.insert(key("c"), 1, cached("c"));
+
+        assert!(cache.get(&key("b"), 1).is_none());
+        assert_eq!(cache.get(&key("a"), 1).unwrap().ids, ["a"]);
+        assert_eq!(cache.get(&key("c"), 1).unwrap().ids, ["c"]);
+        assert!(cache.get(&key("c"), 2).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (3, 2, 2));
+    }
+
+    #[test]
+    fn keys_ignore_order_and_the_cache_flag() {
+        let pairs = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(x, y)| (x.to_string(), y.to_string()))
+                .collect()
+        };
+        assert!(
+            CacheKey::new(&pairs(&[("query", "blood "), ("sort", "name")]), vec![])
+                == CacheKey::new(
+                    &pairs(&[("cache", "true"), ("sort", "name"), ("query", "blood")]),
+                    vec![]
+                )
+        );
+    }
+}
@@ -1,18 +1,99 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
 
-use serde::{Deserialize, Serialize};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::de::{self, Unexpected, Visitor};
+use serde::ser;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
 
-#[derive(Deserialize, Serialize, Debug)]
+/// Every field a card can have in the data files. Keep it in sync with [`Card`].
+pub const CARD_FIELDS: &[&str] = &[
+    "id",
+    "name",
+    "img",
+    "description",
+    "flavor_text",
+    "cost",
+    "health",
+    "defense",
+    "power",
+    "type",
+    "keywords",
+    "kins",
+    "abilities",
+    "artists",
+    "set",
+    "legality",
+    "other",
+    "functions",
+    "faces",
+    "localizations",
+    "collector_number",
+    "released",
+    "rarity",
+    "aliases",
+];
+
+/// The field names of a `fields` parameter like `id,name,cost`, or nothing to keep every field
+/// when it's missing or empty.
+pub fn selected_fields(fields: Option<&str>) -> Option<HashSet<String>> {
+    let fields: HashSet<String> = fields?
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::to_string)
+        .collect();
+    (!fields.is_empty()).then_some(fields)
+}
+
+/// A card that's serialized with only the fields in `fields`, or with every field when it's
+/// `None`. Names that aren't fields of cards are ignored.
+pub struct Projected<'a> {
+    pub card: Cow<'a, Card>,
+    pub fields: Option<&'a HashSet<String>>,
+}
+
+impl Serialize for Projected<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let Some(fields) = self.fields else {
+            return self.card.serialize(serializer);
+        };
+        match serde_json::to_value(&*self.card).map_err(ser::Error::custom)? {
+            serde_json::Value::Object(card) => {
+                serializer.collect_map(card.iter().filter(|(key, _)| fields.contains(key.as_str())))
+            }
+            card => card.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Card {
     pub id: String,
     pub name: String,
     #[serde(default)]
     pub img: Vec<String>,
     pub description: String,
-    pub cost: usize,
-    pub health: usize,
-    pub defense: usize,
-    pub power: usize,
+    /// Text printed on the card that has no effect on the game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flavor_text: Option<String>,
+    /// Missing or `null` for cards that have no cost at all, like tokens.
+    #[serde(default)]
+    pub cost: Stat,
+    /// Missing or `null` for cards that have no stats, like commands. A 0 is a stat of 0, not a
+    /// missing one.
+    #[serde(default)]
+    pub health: Stat,
+    #[serde(default)]
+    pub defense: Stat,
+    #[serde(default)]
+    pub power: Stat,
     pub r#type: String,
     #[serde(default)]
     pub keywords: Vec<Keyword>,
@@ -23,41 +104,412 @@ pub struct Card {
     #[serde(default)]
     pub artists: Vec<String>,
     pub set: String,
-    pub legality: HashMap<String, String>,
+    pub legality: HashMap<String, FormatLegality>,
     #[serde(default)]
     pub other: Vec<String>,
     #[serde(default)]
     pub functions: Vec<String>,
+    /// Extra faces of multi-faced cards. The top-level fields describe the front face.
+    #[serde(default)]
+    pub faces: Vec<CardFace>,
+    /// Translated text, keyed by language tag (`es`, `pt-BR`...).
+    #[serde(default)]
+    pub localizations: HashMap<String, LocalizedText>,
+    /// Position of the card in its set. A string because numbers like "12a" exist.
+    #[serde(default)]
+    pub collector_number: String,
+    /// Release date in the `YYYY-MM-DD` format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub released: Option<String>,
+    /// How rare the card is, like `common`. Cards from before rarities were tracked don't have it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rarity: Option<String>,
+    /// Former ids of the card, so links made before a rename keep working.
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// The text searches compare, built when the card is added to a catalog so searches don't
+    /// fold every card again.
+    #[serde(skip)]
+    pub search_cache: Option<SearchCache>,
+}
+
+/// The text of a card run through [`fold`], as searches compare it. Lists are joined with line
+/// breaks, which words of a query never have, so a match never spans two entries.
+#[derive(Debug, Clone)]
+pub struct SearchCache {
+    pub name: String,
+    pub description: String,
+    pub abilities: String,
+    pub r#type: String,
+    pub kins: String,
+    /// Names of the keywords of every face.
+    pub keywords: String,
+    /// Name and description of every extra face.
+    pub faces: Vec<(String, String)>,
+}
+
+impl SearchCache {
+    pub fn new(card: &Card) -> Self {
+        let keywords: Vec<&str> = card
+            .keywords
+            .iter()
+            .chain(card.faces.iter().flat_map(|x| &x.keywords))
+            .map(|x| x.name.as_str())
+            .collect();
+        Self {
+            name: fold(&card.name),
+            description: fold(&card.description),
+            abilities: fold(&card.abilities.join("\n")),
+            r#type: fold(&card.r#type),
+            kins: fold(&card.kins.join("\n")),
+            keywords: fold(&keywords.join("\n")),
+            faces: card
+                .faces
+                .iter()
+                .map(|x| (fold(&x.name), fold(&x.description)))
+                .collect(),
+        }
+    }
 }
 
 impl Card {
-    pub fn get_cost(&self) -> usize {
+    pub fn get_cost(&self) -> Stat {
         self.cost
     }
+    /// Builds the card's [`SearchCache`] for [`Card::search_text`].
+    pub fn build_search_cache(&mut self) {
+        self.search_cache = Some(SearchCache::new(self));
+    }
+    /// The card's [`SearchCache`], built now if it wasn't already.
+    pub fn search_text(&self) -> Cow<'_, SearchCache> {
+        match &self.search_cache {
+            Some(cache) => Cow::Borrowed(cache),
+            None => Cow::Owned(SearchCache::new(self)),
+        }
+    }
     pub fn get_name(&self) -> &str {
         &self.name
     }
     pub fn get_type(&self) -> &str {
         &self.r#type
     }
+    /// The type of the card, read ignoring case. The `type` field itself keeps the text as it was
+    /// written, so cards are sent exactly as they are in the data.
+    pub fn card_type(&self) -> CardType {
+        CardType::from(self.r#type.as_str())
+    }
     pub fn get_kins(&self) -> &[String] {
         &self.kins
     }
     pub fn get_keywords(&self) -> &[Keyword] {
         &self.keywords
     }
-    pub fn get_health(&self) -> usize {
+    pub fn get_health(&self) -> Stat {
         self.health
     }
-    pub fn get_power(&self) -> usize {
+    pub fn get_power(&self) -> Stat {
         self.power
     }
-    pub fn get_defense(&self) -> usize {
+    pub fn get_defense(&self) -> Stat {
         self.defense
     }
+    pub fn get_rarity(&self) -> Option<&str> {
+        self.rarity.as_deref()
+    }
+    /// The name of the front face followed by the names of every other face.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.name.as_str()).chain(self.faces.iter().map(|x| x.name.as_str()))
+    }
+    /// Links to the images of the card under `base_url`, front first. Images listed in `img` are
+    /// used when there are any, and otherwise every face has an image named after it.
+    pub fn image_urls(&self, base_url: &str) -> Vec<String> {
+        let base_url = base_url.trim_end_matches('/');
+        let url = |name: &str| format!("{base_url}/{}.png", image_file_name(name));
+        if self.img.is_empty() {
+            self.names().map(url).collect()
+        } else {
+            self.img.iter().map(|x| url(x)).collect()
+        }
+    }
+    /// A readable name for the card in links, like `vampire-mantis`: its name made a [`slug`],
+    /// or its id when the name has no letters or digits. Cards can share it, so the catalog
+    /// tells them apart with [`Catalog::slug`](crate::catalog::Catalog::slug).
+    pub fn slug(&self) -> String {
+        match slug(&self.name) {
+            name if name.is_empty() => slug(&self.id),
+            name => name,
+        }
+    }
+    /// The functions of the card, trimmed and lowercase so they can be compared.
+    pub fn normalized_functions(&self) -> HashSet<String> {
+        self.functions
+            .iter()
+            .map(|x| x.trim().to_lowercase())
+            .filter(|x| !x.is_empty())
+            .collect()
+    }
+    /// The release date of the card, if it has a valid one.
+    pub fn release_date(&self) -> Option<&str> {
+        self.released.as_deref().filter(|x| is_iso_date(x))
+    }
+    /// Whether the card has the keyword `keyword` with a card description that `target` fits,
+    /// like a creature with Devours that could devour `target`.
+    pub fn keyword_targets(&self, keyword: &str, target: &Card) -> bool {
+        self.keywords.iter().any(|x| {
+            x.name.eq_ignore_ascii_case(keyword)
+                && matches!(&x.data, Some(KeywordData::CardID(id)) if id.fits(target))
+        })
+    }
+    /// Orders cards as they appear in their set. Cards without a collector number go after the
+    /// ones that have it, and ties are broken by name.
+    pub fn collector_cmp(&self, other: &Card) -> Ordering {
+        match (
+            self.collector_number.is_empty(),
+            other.collector_number.is_empty(),
+        ) {
+            (false, false) => natural_cmp(&self.collector_number, &other.collector_number),
+            (false, true) => Ordering::Less,
+            (true, false) => Ordering::Greater,
+            (true, true) => Ordering::Equal,
+        }
+        .then_with(|| self.name.cmp(&other.name))
+    }
+    /// The translation for the first of `languages` the card has one for. A language like `es-MX`
+    /// also accepts a translation tagged `es`.
+    pub fn localization(&self, languages: &[String]) -> Option<&LocalizedText> {
+        languages.iter().find_map(|language| {
+            let language = language.to_lowercase();
+            let primary = language.split('-').next().unwrap_or(language.as_str());
+            self.localizations
+                .iter()
+                .find(|(tag, _)| {
+                    let tag = tag.to_lowercase();
+                    tag == language || tag == primary
+                })
+                .map(|(_, text)| text)
+        })
+    }
+    /// The card with its name, description and flavor text replaced by their translations, keeping
+    /// the original text for anything that isn't translated.
+    pub fn localized(&self, languages: &[String]) -> Cow<'_, Card> {
+        let Some(text) = self.localization(languages) else {
+            return Cow::Borrowed(self);
+        };
+        let mut card = self.clone();
+        card.search_cache = None;
+        if let Some(name) = &text.name {
+            card.name.clone_from(name);
+        }
+        if let Some(description) = &text.description {
+            card.description.clone_from(description);
+        }
+        if let Some(flavor) = &text.flavor {
+            card.flavor_text = Some(flavor.clone());
+        }
+        Cow::Owned(card)
+    }
+}
+
+/// A plain text rendering of the card for places that can't show its image, like chat bots. Lines
+/// for kins, stats, keywords and abilities are left out when the card has none.
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cost {
+            Stat::None => writeln!(f, "{}", self.name)?,
+            cost => writeln!(f, "{} ({cost})", self.name)?,
+        }
+        write!(f, "{}", self.r#type)?;
+        if !self.kins.is_empty() {
+            write!(f, " - {}", self.kins.join(", "))?;
+        }
+        if [self.power, self.health, self.defense] != [Stat::None; 3] {
+            write!(
+                f,
+                "\nP/H/D: {}/{}/{}",
+                self.power, self.health, self.defense
+            )?;
+        }
+        if !self.keywords.is_empty() {
+            let keywords: Vec<String> = self.keywords.iter().map(ToString::to_string).collect();
+            write!(f, "\n{}", keywords.join(", "))?;
+        }
+        for ability in &self.abilities {
+            write!(f, "\n{ability}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Characters left as they are in image URLs. Everything else is percent-encoded.
+const IMAGE_NAME: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Image files are named like the card without spaces or accents, so `Follower Of Nä` is
+/// `FollowerOfNa`. Whatever isn't safe in a URL after that is percent-encoded.
+fn image_file_name(name: &str) -> String {
+    let name: String = name
+        .nfd()
+        .filter(|x| !x.is_whitespace() && !is_combining_mark(*x))
+        .collect();
+    utf8_percent_encode(&name, IMAGE_NAME).to_string()
+}
+
+/// Turns `text` into the form it's compared in when searching: lowercase, without accents or
+/// other combining marks, and with [`ascii_punctuation`], so `Härkönnen` and `harkonnen` are the
+/// same. Lowercasing stands in for full case folding, so `ß` doesn't become `ss`.
+pub fn fold(text: &str) -> String {
+    fold_chars(text.chars()).collect()
+}
+
+/// `text` [`fold`]ed, without apostrophes, and with every run of characters other than letters
+/// and digits turned into one `-`, so `Mantis's Blood` becomes `mantiss-blood`.
+pub fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for c in fold_chars(text.chars()).filter(|x| *x != '\'') {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.truncate(slug.trim_end_matches('-').len());
+    slug
+}
+
+/// [`fold`] for text that's read one character at a time.
+pub fn fold_chars(text: impl Iterator<Item = char>) -> impl Iterator<Item = char> {
+    text.flat_map(char::to_lowercase)
+        .nfd()
+        .filter(|x| !is_combining_mark(*x))
+        .map(ascii_punctuation)
+}
+
+/// The ASCII version of typographic quotes, apostrophes and dashes, which card data is written
+/// with.
+pub fn ascii_punctuation(c: char) -> char {
+    match c {
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => '\'',
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => '"',
+        '\u{2010}'..='\u{2015}' | '\u{2212}' => '-',
+        c => c,
+    }
+}
+
+/// Writes text typed or pasted by users, like queries, the way card data is: in NFC, with
+/// [`ascii_punctuation`], and with every run of whitespace turned into one space.
+pub fn normalize_text(text: &str) -> String {
+    let mut normalized = String::with_capacity(text.len());
+    for c in text.trim().nfc().map(ascii_punctuation) {
+        if !c.is_whitespace() {
+            normalized.push(c);
+        } else if !normalized.ends_with(' ') {
+            normalized.push(' ');
+        }
+    }
+    normalized
+}
+
+/// Whether `date` is a real calendar date written as `YYYY-MM-DD`. Dates in this format sort
+/// chronologically when compared as strings.
+pub fn is_iso_date(date: &str) -> bool {
+    let well_formed = date.len() == 10
+        && date.bytes().enumerate().all(|(i, x)| match i {
+            4 | 7 => x == b'-',
+            _ => x.is_ascii_digit(),
+        });
+    if !well_formed {
+        return false;
+    }
+    let (Ok(year), Ok(month), Ok(day)) = (
+        date[0..4].parse::<u32>(),
+        date[5..7].parse::<u32>(),
+        date[8..10].parse::<u32>(),
+    ) else {
+        return false;
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+    (1..=days).contains(&day)
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+/// Compares strings so runs of digits are ordered by their numeric value: "2" < "10" < "12a".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        let ordering = match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                x.len().cmp(&y.len()).then_with(|| x.cmp(&y))
+            }
+            (Some(x), Some(y)) => {
+                a.next();
+                b.next();
+                x.to_lowercase().cmp(y.to_lowercase())
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Consumes a run of digits, returning it without leading zeros.
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        if !(number.is_empty() && digit == '0') {
+            number.push(digit);
+        }
+    }
+    number
+}
+
+/// Translated text for a card. Missing fields fall back to the card's own text.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct LocalizedText {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flavor: Option<String>,
+}
+
+/// The displayable part of one face of a multi-faced card.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CardFace {
+    pub name: String,
+    #[serde(default)]
+    pub img: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub cost: Stat,
+    #[serde(default)]
+    pub health: Stat,
+    #[serde(default)]
+    pub defense: Stat,
+    #[serde(default)]
+    pub power: Stat,
+    #[serde(default)]
+    pub keywords: Vec<Keyword>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct CardID {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
@@ -72,10 +524,16 @@ pub struct CardID {
     #[serde(default)]
     pub kins: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, deserialize_with = "number_or_string")]
+    pub cost: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, deserialize_with = "number_or_string")]
     pub health: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, deserialize_with = "number_or_string")]
     pub defense: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, deserialize_with = "number_or_string")]
     pub power: Option<usize>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(default)]
@@ -85,16 +543,728 @@ pub struct CardID {
     pub functions: Option<Vec<String>>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
-#[serde(tag = "type")]
+impl CardID {
+    /// Whether `card` satisfies every field of this description. Stats must be equal, text fields
+    /// must appear in the card's text ignoring case, and every listed kin, keyword, ability and
+    /// function must be on the card.
+    pub fn fits(&self, card: &Card) -> bool {
+        fn contains(text: &str, part: &str) -> bool {
+            text.to_lowercase().contains(&part.to_lowercase())
+        }
+        fn subset<'a>(
+            wanted: Option<&'a [String]>,
+            present: impl Iterator<Item = &'a str> + Clone,
+        ) -> bool {
+            wanted.unwrap_or_default().iter().all(|x| {
+                present
+                    .clone()
+                    .any(|y| y.trim().eq_ignore_ascii_case(x.trim()))
+            })
+        }
+
+        let text_fits = self.name.as_ref().is_none_or(|x| contains(&card.name, x))
+            && self
+                .description
+                .as_ref()
+                .is_none_or(|x| contains(&card.description, x))
+            && self
+                .r#type
+                .as_ref()
+                .is_none_or(|x| contains(&card.r#type, x));
+
+        let stats_fit = self.cost.is_none_or(|x| card.cost == Stat::Fixed(x))
+            && self.health.is_none_or(|x| card.health == Stat::Fixed(x))
+            && self.defense.is_none_or(|x| card.defense == Stat::Fixed(x))
+            && self.power.is_none_or(|x| card.power == Stat::Fixed(x));
+
+        let keywords: Option<Vec<String>> = self
+            .keywords
+            .as_ref()
+            .map(|x| x.iter().map(|x| x.name.clone()).collect());
+
+        text_fits
+            && stats_fit
+            && subset(self.kins.as_deref(), card.kins.iter().map(String::as_str))
+            && subset(
+                keywords.as_deref(),
+                card.keywords.iter().map(|x| x.name.as_str()),
+            )
+            && subset(
+                self.abilities.as_deref(),
+                card.abilities.iter().map(String::as_str),
+            )
+            && subset(
+                self.functions.as_deref(),
+                card.functions.iter().map(String::as_str),
+            )
+    }
+}
+
+/// A short description like `creature named "Mantis" with kins insect, power 2`.
+impl fmt::Display for CardID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.r#type.as_deref().unwrap_or("card"))?;
+        if let Some(name) = &self.name {
+            write!(f, " named \"{name}\"")?;
+        }
+
+        let mut traits = vec![];
+        if let Some(description) = &self.description {
+            traits.push(format!("\"{description}\" in its text"));
+        }
+        if let Some(kins) = self.kins.as_ref().filter(|x| !x.is_empty()) {
+            traits.push(format!("kins {}", kins.join(", ")));
+        }
+        if let Some(keywords) = self.keywords.as_ref().filter(|x| !x.is_empty()) {
+            let keywords: Vec<String> = keywords.iter().map(ToString::to_string).collect();
+            traits.push(format!("keywords {}", keywords.join(", ")));
+        }
+        for (stat, value) in [
+            ("cost", self.cost),
+            ("power", self.power),
+            ("health", self.health),
+            ("defense", self.defense),
+        ] {
+            if let Some(value) = value {
+                traits.push(format!("{stat} {value}"));
+            }
+        }
+        if let Some(abilities) = self.abilities.as_ref().filter(|x| !x.is_empty()) {
+            traits.push(format!("abilities {}", abilities.join(", ")));
+        }
+        if let Some(functions) = self.functions.as_ref().filter(|x| !x.is_empty()) {
+            traits.push(format!("functions {}", functions.join(", ")));
+        }
+
+        if !traits.is_empty() {
+            write!(f, " with {}", traits.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Extra information carried by a keyword, like the cards Devours can target or the amount of
+/// Armor.
+///
+/// Payloads are written as objects tagged with their `type`, like
+/// `{ "type": "Number", "value": 2 }`. Plain numbers and strings are accepted as shorthands.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(from = "KeywordDataRepr", into = "TaggedKeywordData")]
 pub enum KeywordData {
     CardID(CardID),
     String(String),
+    Number(i64),
+    NameAndNumber { name: String, value: i64 },
+}
+
+impl KeywordData {
+    /// The number carried by the payload, if it has one.
+    pub fn value(&self) -> Option<i64> {
+        match self {
+            KeywordData::Number(x) | KeywordData::NameAndNumber { value: x, .. } => Some(*x),
+            KeywordData::CardID(_) | KeywordData::String(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for KeywordData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeywordData::CardID(x) => write!(f, "{x}"),
+            KeywordData::String(x) => write!(f, "{x}"),
+            KeywordData::Number(x) => write!(f, "{x}"),
+            KeywordData::NameAndNumber { name, value } => write!(f, "{name} {value}"),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(tag = "type")]
+enum TaggedKeywordData {
+    CardID(CardID),
+    String { value: String },
+    Number { value: i64 },
+    NameAndNumber { name: String, value: i64 },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum KeywordDataRepr {
+    Number(i64),
+    String(String),
+    Tagged(TaggedKeywordData),
+}
+
+impl From<KeywordDataRepr> for KeywordData {
+    fn from(value: KeywordDataRepr) -> Self {
+        match value {
+            KeywordDataRepr::Number(x)
+            | KeywordDataRepr::Tagged(TaggedKeywordData::Number { value: x }) => {
+                KeywordData::Number(x)
+            }
+            KeywordDataRepr::String(x)
+            | KeywordDataRepr::Tagged(TaggedKeywordData::String { value: x }) => {
+                KeywordData::String(x)
+            }
+            KeywordDataRepr::Tagged(TaggedKeywordData::CardID(x)) => KeywordData::CardID(x),
+            KeywordDataRepr::Tagged(TaggedKeywordData::NameAndNumber { name, value }) => {
+                KeywordData::NameAndNumber { name, value }
+            }
+        }
+    }
+}
+
+impl From<KeywordData> for TaggedKeywordData {
+    fn from(value: KeywordData) -> Self {
+        match value {
+            KeywordData::CardID(x) => TaggedKeywordData::CardID(x),
+            KeywordData::String(value) => TaggedKeywordData::String { value },
+            KeywordData::Number(value) => TaggedKeywordData::Number { value },
+            KeywordData::NameAndNumber { name, value } => {
+                TaggedKeywordData::NameAndNumber { name, value }
+            }
+        }
+    }
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Keyword {
     pub name: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub data: Option<KeywordData>,
 }
+
+impl Keyword {
+    /// The number carried by the keyword, like the 2 of Armor 2.
+    pub fn value(&self) -> Option<i64> {
+        self.data.as_ref().and_then(KeywordData::value)
+    }
+}
+
+/// The keyword followed by its payload, like `armor 2` or `devours (creature with power 1)`.
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.data {
+            None => write!(f, "{}", self.name),
+            Some(data @ KeywordData::CardID(_)) => write!(f, "{} ({data})", self.name),
+            Some(data) => write!(f, "{} {data}", self.name),
+        }
+    }
+}
+
+/// Whether a card can be played in a format.
+///
+/// Reading is case-insensitive and also accepts the one-letter codes used in the data files (`y`,
+/// `n`, `b`, `r`). Anything else is kept as `Unknown` so it can be reported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Legality {
+    Legal,
+    Banned,
+    Restricted,
+    NotLegal,
+    Unknown(String),
+}
+
+impl Legality {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Legality::Legal => "legal",
+            Legality::Banned => "banned",
+            Legality::Restricted => "restricted",
+            Legality::NotLegal => "not_legal",
+            Legality::Unknown(x) => x,
+        }
+    }
+
+    /// Whether the card can be in a deck at all. Restricted cards can, with fewer copies.
+    pub fn is_playable(&self) -> bool {
+        matches!(self, Legality::Legal | Legality::Restricted)
+    }
+}
+
+impl From<&str> for Legality {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "legal" | "y" | "yes" => Legality::Legal,
+            "banned" | "b" => Legality::Banned,
+            "restricted" | "r" => Legality::Restricted,
+            "not_legal" | "not legal" | "n" | "no" => Legality::NotLegal,
+            _ => Legality::Unknown(value.to_string()),
+        }
+    }
+}
+
+impl Serialize for Legality {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// The legality of a card in one format, as it's written in the card data. It's sent as it was
+/// written, so a `y` stays a `y`, and [`FormatLegality::status`] is what it means.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatLegality {
+    pub status: Legality,
+    pub text: String,
+}
+
+impl From<&str> for FormatLegality {
+    fn from(value: &str) -> Self {
+        Self {
+            status: Legality::from(value),
+            text: value.to_string(),
+        }
+    }
+}
+
+impl Serialize for FormatLegality {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.text)
+    }
+}
+
+impl<'de> Deserialize<'de> for FormatLegality {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|x| FormatLegality::from(x.as_str()))
+    }
+}
+
+/// The kind of a card. Types are read ignoring case, and anything else is kept as `Other`. Types
+/// are listed in the order of the variants, with other types after them alphabetically.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CardType {
+    Creature,
+    Command,
+    CommandVestige,
+    BloodFlask,
+    Other(String),
+}
+
+impl CardType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            CardType::Creature => "creature",
+            CardType::Command => "command",
+            CardType::CommandVestige => "command vestige",
+            CardType::BloodFlask => "blood flask",
+            CardType::Other(x) => x,
+        }
+    }
+}
+
+impl From<&str> for CardType {
+    fn from(value: &str) -> Self {
+        match value.trim().to_lowercase().as_str() {
+            "creature" => CardType::Creature,
+            "command" => CardType::Command,
+            "command vestige" => CardType::CommandVestige,
+            "blood flask" => CardType::BloodFlask,
+            _ => CardType::Other(value.trim().to_string()),
+        }
+    }
+}
+
+impl fmt::Display for CardType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for CardType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CardType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|x| CardType::from(x.as_str()))
+    }
+}
+
+/// A cost or stat of a card. Most are plain numbers, but some cards have values that depend on the
+/// game ("X", "*").
+///
+/// In JSON, fixed stats are plain integers and variable ones are strings, so existing card files
+/// keep loading unchanged. Numbers written as strings, like `"3"`, are read as fixed stats too,
+/// since some of the tools that write card files quote them.
+///
+/// A stat that's left out or `null` is [`Stat::None`], which no numeric filter matches. Zeros are
+/// still read as a stat of 0 and keep matching filters like `power=0`, so card files that used 0
+/// to mean the card has no such stat should leave the field out or set it to `null` instead.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Stat {
+    Fixed(usize),
+    /// A value that depends on the game, with the symbol it's written with, like `X` or `*`.
+    Variable(char),
+    #[default]
+    None,
+}
+
+impl Stat {
+    /// The value of the stat, if it's a fixed number.
+    pub fn fixed(self) -> Option<usize> {
+        match self {
+            Stat::Fixed(x) => Some(x),
+            Stat::Variable(_) | Stat::None => None,
+        }
+    }
+}
+
+/// Written as the number, the symbol of variable stats and `-` for absent ones.
+impl fmt::Display for Stat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stat::Fixed(x) => write!(f, "{x}"),
+            Stat::Variable(x) => write!(f, "{x}"),
+            Stat::None => write!(f, "-"),
+        }
+    }
+}
+
+impl Serialize for Stat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Stat::Fixed(x) => x.serialize(serializer),
+            Stat::Variable(x) => serializer.serialize_char(*x),
+            Stat::None => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Stat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(StatVisitor)
+    }
+}
+
+struct StatVisitor;
+
+impl<'de> Visitor<'de> for StatVisitor {
+    type Value = Stat;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a non-negative integer, \"X\", \"*\" or null")
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Stat, E> {
+        usize::try_from(v)
+            .map(Stat::Fixed)
+            .map_err(|_| E::invalid_value(Unexpected::Unsigned(v), &self))
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Stat, E> {
+        usize::try_from(v)
+            .map(Stat::Fixed)
+            .map_err(|_| E::invalid_value(Unexpected::Signed(v), &self))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Stat, E> {
+        match v.trim() {
+            x @ ("X" | "x" | "*") => Ok(Stat::Variable(x.chars().next().unwrap_or('X'))),
+            x => x
+                .parse()
+                .map(Stat::Fixed)
+                .map_err(|_| E::invalid_value(Unexpected::Str(v), &self)),
+        }
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Stat, E> {
+        Ok(Stat::None)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<Stat, E> {
+        Ok(Stat::None)
+    }
+}
+
+/// Reads an optional number that may be written as a string, like the stats of [`Stat`].
+fn number_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    match Option::<Stat>::deserialize(deserializer)? {
+        Some(Stat::Fixed(x)) => Ok(Some(x)),
+        None | Some(Stat::None) => Ok(None),
+        Some(Stat::Variable(x)) => Err(de::Error::invalid_value(
+            Unexpected::Char(x),
+            &"a non-negative integer",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_cards::card_with;
+
+    fn mantis() -> Card {
+        card_with(
+            "vampire_mantis",
+            json!({
+                "name": "Vampire Mantis",
+                "description": "When Killed: Gain one additional blood",
+                "cost": 3,
+                "health": 3,
+                "defense": 2,
+                "power": 2,
+                "type": "creature",
+                "kins": ["undead", "insect"],
+                "keywords": [{ "name": "bifurcated strike" }],
+                "abilities": ["When Killed: Gain one additional blood."],
+                "functions": ["gain blood"],
+                "set": "The Forest",
+            }),
+        )
+    }
+
+    fn id(description: serde_json::Value) -> CardID {
+        serde_json::from_value(description).unwrap()
+    }
+
+    #[test]
+    fn plain_text_has_one_line_per_part() {
+        assert_eq!(
+            mantis().to_string(),
+            "Vampire Mantis (3)\ncreature - undead, insect\nP/H/D: 2/3/2\nbifurcated strike\n\
+             When Killed: Gain one additional blood."
+        );
+
+        let mut card = mantis();
+        card.kins.clear();
+        card.abilities.clear();
+        card.keywords = vec![serde_json::from_value(json!({
+            "name": "devours",
+            "data": { "type": "CardID", "kins": ["insect"], "power": 1 },
+        }))
+        .unwrap()];
+        assert_eq!(
+            card.to_string(),
+            "Vampire Mantis (3)\ncreature\nP/H/D: 2/3/2\ndevours (card with kins insect, power 1)"
+        );
+    }
+
+    #[test]
+    fn image_urls_are_encoded() {
+        let base = "https://example.com/cards/";
+        let named = |name: &str| {
+            let mut card = mantis();
+            card.name = name.to_string();
+            card.image_urls(base)
+        };
+        assert_eq!(
+            named("Vampire Mantis"),
+            ["https://example.com/cards/VampireMantis.png"]
+        );
+        assert_eq!(
+            named("Mother's Kiss"),
+            ["https://example.com/cards/Mother%27sKiss.png"]
+        );
+        assert_eq!(
+            named("Blood, Sweat"),
+            ["https://example.com/cards/Blood%2CSweat.png"]
+        );
+        assert_eq!(
+            named("Follower Of Nä"),
+            ["https://example.com/cards/FollowerOfNa.png"]
+        );
+        assert_eq!(named("Follower Of Na\u{308}"), named("Follower Of Nä"));
+        assert_eq!(named("Ärch"), ["https://example.com/cards/Arch.png"]);
+        assert_eq!(named("Størm"), ["https://example.com/cards/St%C3%B8rm.png"]);
+
+        let mut card = mantis();
+        card.img = vec!["Blight".to_string(), "Blight2".to_string()];
+        assert_eq!(
+            card.image_urls(base),
+            [
+                "https://example.com/cards/Blight.png",
+                "https://example.com/cards/Blight2.png"
+            ]
+        );
+    }
+
+    #[test]
+    fn card_fields_match_the_struct() {
+        let mut card = mantis();
+        card.released = Some("2024-01-01".to_string());
+        card.rarity = Some("common".to_string());
+        let serialized = serde_json::to_value(&card).unwrap();
+        let mut fields: Vec<&str> = serialized
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        let mut known = CARD_FIELDS.to_vec();
+        fields.sort_unstable();
+        known.sort_unstable();
+        assert_eq!(fields, known);
+    }
+
+    #[test]
+    fn empty_description_fits_anything() {
+        assert!(id(json!({})).fits(&mantis()));
+    }
+
+    #[test]
+    fn text_fields_are_case_insensitive_substrings() {
+        let card = mantis();
+        assert!(id(json!({ "name": "mantis" })).fits(&card));
+        assert!(id(json!({ "description": "ADDITIONAL BLOOD" })).fits(&card));
+        assert!(!id(json!({ "name": "spider" })).fits(&card));
+        assert!(!id(json!({ "name": "mantis", "description": "draw" })).fits(&card));
+    }
+
+    #[test]
+    fn stats_must_be_equal() {
+        let card = mantis();
+        assert!(id(json!({ "health": 3, "defense": 2, "power": 2 })).fits(&card));
+        assert!(!id(json!({ "health": 2 })).fits(&card));
+        assert!(!id(json!({ "health": 3, "power": 3 })).fits(&card));
+    }
+
+    #[test]
+    fn lists_use_subset_semantics() {
+        let card = mantis();
+        assert!(id(json!({ "kins": ["insect"] })).fits(&card));
+        assert!(id(json!({ "kins": ["Insect", "undead"] })).fits(&card));
+        assert!(!id(json!({ "kins": ["insect", "plant"] })).fits(&card));
+        assert!(id(json!({ "keywords": [{ "name": "Bifurcated Strike" }] })).fits(&card));
+        assert!(!id(json!({ "keywords": [{ "name": "flying" }] })).fits(&card));
+        assert!(
+            id(json!({ "abilities": ["When Killed: Gain one additional blood."] })).fits(&card)
+        );
+        assert!(id(json!({ "functions": ["gain blood"] })).fits(&card));
+        assert!(!id(json!({ "functions": ["gain blood", "draw cards"] })).fits(&card));
+    }
+
+    #[test]
+    fn empty_lists_fit_anything() {
+        let mut card = mantis();
+        card.kins.clear();
+        card.keywords.clear();
+        let description =
+            id(json!({ "kins": [], "keywords": [], "abilities": [], "functions": [] }));
+        assert!(description.fits(&card));
+        assert!(description.fits(&mantis()));
+        assert!(!id(json!({ "kins": ["insect"] })).fits(&card));
+    }
+
+    #[test]
+    fn every_field_is_checked_together() {
+        let card = mantis();
+        let description = id(json!({
+            "name": "vampire",
+            "kins": ["insect"],
+            "keywords": [{ "name": "bifurcated strike" }],
+            "health": 3,
+        }));
+        assert!(description.fits(&card));
+        let mut weaker = card.clone();
+        weaker.health = Stat::Fixed(1);
+        assert!(!description.fits(&weaker));
+    }
+
+    #[test]
+    fn stats_can_be_strings() {
+        let mut card = serde_json::to_value(mantis()).unwrap();
+        card["cost"] = json!("3");
+        card["health"] = json!(" 2 ");
+        card["power"] = json!("X");
+        card["defense"] = json!("*");
+        let card: Card = serde_json::from_value(card).unwrap();
+        assert_eq!(
+            (card.cost, card.health, card.power, card.defense),
+            (
+                Stat::Fixed(3),
+                Stat::Fixed(2),
+                Stat::Variable('X'),
+                Stat::Variable('*')
+            )
+        );
+        let sent = serde_json::to_value(&card).unwrap();
+        assert_eq!(
+            (&sent["power"], &sent["defense"]),
+            (&json!("X"), &json!("*"))
+        );
+        assert_eq!(card.defense.to_string(), "*");
+        assert!(serde_json::from_value::<Stat>(json!("-1")).is_err());
+        assert!(serde_json::from_value::<Stat>(json!("three")).is_err());
+
+        assert!(id(json!({ "health": "3", "defense": 2 })).fits(&mantis()));
+        assert!(serde_json::from_value::<CardID>(json!({ "power": "X" })).is_err());
+    }
+
+    #[test]
+    fn projections_keep_the_selected_fields() {
+        let fields = selected_fields(Some("id, name,cost,nonsense,")).unwrap();
+        let card = Projected {
+            card: Cow::Owned(mantis()),
+            fields: Some(&fields),
+        };
+        assert_eq!(
+            serde_json::to_value(&card).unwrap(),
+            json!({ "id": "vampire_mantis", "name": "Vampire Mantis", "cost": 3 })
+        );
+        assert!(selected_fields(Some(" , ")).is_none());
+        let every = Projected {
+            card: Cow::Owned(mantis()),
+            fields: None,
+        };
+        assert_eq!(
+            serde_json::to_value(&every).unwrap(),
+            serde_json::to_value(mantis()).unwrap()
+        );
+    }
+
+    #[test]
+    fn legality_is_sent_as_written() {
+        let mut card = mantis();
+        card.legality = HashMap::from([
+            ("standard".to_string(), FormatLegality::from("y")),
+            ("draft".to_string(), FormatLegality::from("Banned")),
+        ]);
+        assert_eq!(card.legality["standard"].status, Legality::Legal);
+        assert_eq!(card.legality["draft"].status, Legality::Banned);
+        assert_eq!(
+            serde_json::to_value(&card).unwrap()["legality"],
+            json!({"standard": "y", "draft": "Banned"})
+        );
+    }
+
+    #[test]
+    fn devours_checks_the_cost() {
+        // The Devours of infected_fly in cards.json.
+        let devours: Keyword = serde_json::from_value(json!({
+            "name": "devours",
+            "data": {"type": "CardID", "cost": 1, "kins": ["insect"]},
+        }))
+        .unwrap();
+        let mut fly = mantis();
+        fly.keywords = vec![devours];
+
+        let mut target = mantis();
+        assert!(!fly.keyword_targets("devours", &target));
+        target.cost = Stat::Fixed(1);
+        assert!(fly.keyword_targets("devours", &target));
+        let Some(KeywordData::CardID(id)) = &fly.keywords[0].data else {
+            panic!("Devours should target a card");
+        };
+        assert_eq!(id.to_string(), "card with kins insect, cost 1");
+    }
+
+    #[test]
+    fn translations_replace_the_flavor_text() {
+        let mut card = mantis();
+        card.flavor_text = Some("It bites.".to_string());
+        card.localizations = serde_json::from_value(json!({
+            "es": {"name": "Mantis", "flavor": "Muerde."},
+        }))
+        .unwrap();
+        let localized = card.localized(&["es-MX".to_string()]);
+        assert_eq!(localized.name, "Mantis");
+        assert_eq!(localized.description, card.description);
+        assert_eq!(localized.flavor_text.as_deref(), Some("Muerde."));
+        let original = card.localized(&["fr".to_string()]);
+        assert_eq!(original.flavor_text.as_deref(), Some("It bites."));
+    }
+}
@@ -0,0 +1,402 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::cards::{self, fold, Card};
+use crate::kins::KinHierarchy;
+use crate::search::{self, NameIndex, Query, WordIndex, SEARCH_CHUNK};
+
+/// Cards checked at once when only the first matches are needed. Enough to be split between
+/// threads, but few enough that a page found early doesn't wait for many more cards.
+const PAGE_BATCH: usize = 8 * SEARCH_CHUNK;
+
+/// Every loaded card together with the indexes built over them. It is always replaced as a whole
+/// so the indexes never disagree with the cards.
+pub struct Catalog {
+    pub cards: HashMap<String, Card>,
+    /// Every id, sorted, to go through the cards in the order of pages.
+    pub sorted_ids: Vec<String>,
+    /// Name of every face of every card, [`fold`]ed, pointing to the card's id.
+    pub names: HashMap<String, String>,
+    /// Lowercase id and aliases of every card, pointing to the card's id.
+    pub ids: HashMap<String, String>,
+    /// [`Card::slug`] of every card, pointing to its id. When cards share a slug, the one with
+    /// the lowest id gets it and the others have their id appended to it, and then a number if
+    /// that is taken too.
+    pub slugs: HashMap<String, String>,
+    /// The slug each card was given in [`slugs`](Catalog::slugs), by id.
+    pub card_slugs: HashMap<String, String>,
+    /// Ids and aliases that more than one card claims. Ids win over aliases, and otherwise the
+    /// card with the lowest id wins.
+    pub id_collisions: Vec<String>,
+    /// Names grouped for typo matching.
+    pub name_index: NameIndex,
+    /// Words of the searched text of every card, to narrow down free text searches.
+    pub words: WordIndex,
+    /// Which kins belong to broader kins. Empty unless set after building the catalog.
+    pub kins: KinHierarchy,
+    /// Release order of each set, from the optional `sets.json` next to the cards. Empty unless
+    /// set after building the catalog.
+    pub set_order: HashMap<String, usize>,
+    /// Goes up by one every time a reload replaces the catalog, starting at 1.
+    pub version: u64,
+}
+
+impl Catalog {
+    pub fn new(mut cards: HashMap<String, Card>) -> Self {
+        for card in cards.values_mut() {
+            card.build_search_cache();
+        }
+        let names = cards
+            .values()
+            .flat_map(|card| card.names().map(|name| (fold(name), card.id.clone())))
+            .collect();
+
+        let mut sorted: Vec<&Card> = cards.values().collect();
+        sorted.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        let mut ids: HashMap<String, String> = HashMap::new();
+        let mut id_collisions = vec![];
+        let canonical = sorted.iter().map(|&card| (card.id.as_str(), card));
+        let aliases = sorted
+            .iter()
+            .flat_map(|&card| card.aliases.iter().map(move |x| (x.as_str(), card)));
+        for (key, card) in canonical.chain(aliases) {
+            match ids.get(&key.to_lowercase()) {
+                Some(owner) if owner != &card.id => id_collisions.push(format!(
+                    "Card {} uses {key:?} as an id or alias, but it already points to {owner}",
+                    card.id
+                )),
+                Some(_) => (),
+                None => {
+                    ids.insert(key.to_lowercase(), card.id.clone());
+                }
+            }
+        }
+
+        let mut slugs = HashMap::new();
+        let mut card_slugs = HashMap::new();
+        for card in &sorted {
+            let mut slug = card.slug();
+            if slugs.contains_key(&slug) {
+                let with_id = format!("{slug}-{}", cards::slug(&card.id));
+                slug = (1..)
+                    .map(|x| match x {
+                        1 => with_id.clone(),
+                        x => format!("{with_id}-{x}"),
+                    })
+                    .find(|x| !slugs.contains_key(x))
+                    .unwrap_or(with_id);
+            }
+            slugs.insert(slug.clone(), card.id.clone());
+            card_slugs.insert(card.id.clone(), slug);
+        }
+
+        let sorted_ids = sorted.iter().map(|x| x.id.clone()).collect();
+        Self {
+            sorted_ids,
+            slugs,
+            card_slugs,
+            name_index: NameIndex::new(cards.values()),
+            words: WordIndex::new(cards.values()),
+            cards,
+            names,
+            ids,
+            id_collisions,
+            kins: KinHierarchy::default(),
+            set_order: HashMap::new(),
+            version: 1,
+        }
+    }
+
+    /// Other cards that share at least one function with `card`, with how many they share, most
+    /// shared first and then by id.
+    pub fn sharing_functions(&self, card: &Card) -> Vec<(&Card, usize)> {
+        let functions = card.normalized_functions();
+        let mut cards: Vec<(&Card, usize)> = self
+            .cards
+            .values()
+            .filter(|x| x.id != card.id)
+            .map(|x| (x, x.normalized_functions().intersection(&functions).count()))
+            .filter(|(_, shared)| *shared > 0)
+            .collect();
+        cards.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.id.cmp(&b.0.id)));
+        cards
+    }
+
+    /// Other cards that refer to `card`, by id: through a keyword with a card description that
+    /// `card` fits, like Devours, or by its name in their description or abilities.
+    pub fn referencing(&self, card: &Card) -> Vec<Reference<'_>> {
+        let name = fold(&card.name);
+        let mut references: Vec<Reference> = self
+            .cards
+            .values()
+            .filter(|x| x.id != card.id)
+            .filter_map(|x| {
+                let keywords: Vec<&str> = x
+                    .keywords
+                    .iter()
+                    .filter(|keyword| x.keyword_targets(&keyword.name, card))
+                    .map(|keyword| keyword.name.as_str())
+                    .collect();
+                let text = x.search_text();
+                let mentions = !name.is_empty()
+                    && (text.description.contains(&name) || text.abilities.contains(&name));
+                (!keywords.is_empty() || mentions).then_some(Reference {
+                    card: x,
+                    keywords,
+                    mentions,
+                })
+            })
+            .collect();
+        references.sort_unstable_by(|a, b| a.card.id.cmp(&b.card.id));
+        references
+    }
+
+    /// The cards that match `query` and whose id comes after `after`, if given. If the query has
+    /// free text, only the cards that [`WordIndex`] lets through are checked.
+    pub fn search(&self, query: &Query, after: Option<&str>) -> Vec<&Card> {
+        let after = |x: &&Card| after.is_none_or(|after| x.id.as_str() > after);
+        match query.candidates(&self.words) {
+            Some(ids) => search::search(
+                query,
+                ids.into_iter()
+                    .filter_map(|x| self.cards.get(x))
+                    .filter(after),
+            ),
+            None => search::search(query, self.cards.values().filter(after)),
+        }
+    }
+
+    /// The first `count` cards, by id, that match `query` and whose id comes after `after`, if
+    /// given. Cards are checked a batch at a time, and no more are once `count` are found.
+    pub fn first_matches(&self, query: &Query, after: Option<&str>, count: usize) -> Vec<&Card> {
+        let start = after.map_or(0, |after| {
+            self.sorted_ids.partition_point(|x| x.as_str() <= after)
+        });
+        let candidates = query.candidates(&self.words);
+        let mut found = vec![];
+        for ids in self.sorted_ids[start..].chunks(PAGE_BATCH) {
+            let cards = ids
+                .iter()
+                .filter(|x| candidates.as_ref().is_none_or(|c| c.contains(x.as_str())))
+                .filter_map(|x| self.cards.get(x));
+            found.extend(search::search(query, cards));
+            if found.len() >= count {
+                break;
+            }
+        }
+        found.truncate(count);
+        found
+    }
+
+    /// Finds a card by its id or any of its aliases, ignoring case.
+    pub fn by_id(&self, id: &str) -> Option<&Card> {
+        self.ids
+            .get(&id.trim().to_lowercase())
+            .and_then(|id| self.cards.get(id))
+    }
+
+    /// The slug that leads to `card` in this catalog, which is [`Card::slug`] unless an earlier
+    /// card has it. Cards that aren't in the catalog get their [`Card::slug`].
+    pub fn slug(&self, card: &Card) -> String {
+        self.card_slugs
+            .get(&card.id)
+            .cloned()
+            .unwrap_or_else(|| card.slug())
+    }
+
+    /// Finds a card by its [`slug`](Catalog::slug), ignoring case.
+    pub fn by_slug(&self, slug: &str) -> Option<&Card> {
+        self.slugs
+            .get(&slug.trim().to_lowercase())
+            .and_then(|id| self.cards.get(id))
+    }
+
+    /// Finds a card by the name of any of its faces, ignoring case and accents.
+    pub fn by_name(&self, name: &str) -> Option<&Card> {
+        self.names
+            .get(&fold(name.trim()))
+            .and_then(|id| self.cards.get(id))
+    }
+
+    /// The ids of the cards that differ between this catalog and `new`. A card counts as changed
+    /// when its serialized JSON differs.
+    pub fn diff(&self, new: &Catalog) -> CatalogDiff {
+        let mut diff = CatalogDiff::default();
+        for (id, card) in &new.cards {
+            match self.cards.get(id) {
+                None => diff.added.push(id.clone()),
+                Some(old) if serde_json::to_value(old).ok() != serde_json::to_value(card).ok() => {
+                    diff.changed.push(id.clone());
+                }
+                Some(_) => (),
+            }
+        }
+        diff.removed = self
+            .cards
+            .keys()
+            .filter(|x| !new.cards.contains_key(*x))
+            .cloned()
+            .collect();
+
+        diff.added.sort_unstable();
+        diff.removed.sort_unstable();
+        diff.changed.sort_unstable();
+        diff
+    }
+}
+
+/// How a card refers to another one.
+pub struct Reference<'a> {
+    pub card: &'a Card,
+    /// Keywords of the card whose card description the other card fits.
+    pub keywords: Vec<&'a str>,
+    /// Whether the card's description or abilities have the other card's name.
+    pub mentions: bool,
+}
+
+/// Cards added, removed and changed between two catalogs, sorted by id.
+#[derive(Serialize, Clone, Default)]
+pub struct CatalogDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use serde_json::json;
+
+    use super::*;
+    use crate::search::QueryParams;
+    use crate::test_cards::numbered;
+
+    fn catalog(count: usize) -> Catalog {
+        let cards = (1..=count)
+            .map(|x| {
+                let card = numbered(x);
+                (card.id.clone(), card)
+            })
+            .collect();
+        Catalog::new(cards)
+    }
+
+    fn query(query: &str) -> Query {
+        web::Query::<QueryParams>::from_query(query)
+            .unwrap()
+            .into_inner()
+            .parse()
+            .unwrap()
+    }
+
+    fn ids(cards: &[&Card]) -> Vec<String> {
+        let mut ids: Vec<String> = cards.iter().map(|x| x.id.clone()).collect();
+        ids.sort_unstable();
+        ids
+    }
+
+    #[test]
+    fn first_matches_stop_at_the_count() {
+        let catalog = catalog(5);
+        let query = query("query=card");
+        assert_eq!(
+            ids(&catalog.first_matches(&query, None, 3)),
+            ["card_1", "card_2", "card_3"]
+        );
+        assert_eq!(
+            ids(&catalog.first_matches(&query, Some("card_3"), 3)),
+            ["card_4", "card_5"]
+        );
+    }
+
+    #[test]
+    fn first_matches_end_exactly_at_a_page_edge() {
+        let catalog = catalog(5);
+        let query = query("query=card&cost=%3E2");
+        // A page of 3 after card_2 looks for 4 cards to know if there's a next page.
+        let found = catalog.first_matches(&query, Some("card_2"), 4);
+        assert_eq!(ids(&found), ["card_3", "card_4", "card_5"]);
+        // A page of 2 finds the card that starts the next one.
+        assert_eq!(catalog.first_matches(&query, Some("card_2"), 3).len(), 3);
+        assert!(catalog.first_matches(&query, Some("card_5"), 3).is_empty());
+    }
+
+    #[test]
+    fn full_searches_find_every_match() {
+        let catalog = catalog(5);
+        assert_eq!(
+            ids(&catalog.search(&query("query=card&cost=%3E2"), Some("card_3"))),
+            ["card_4", "card_5"]
+        );
+        assert_eq!(
+            ids(&catalog.search(&query("query=card&cost=4"), None)),
+            ["card_4"]
+        );
+    }
+
+    #[test]
+    fn shared_slugs_get_the_id() {
+        let mut cards = catalog(2).cards;
+        for card in cards.values_mut() {
+            card.name = "Vampire Mantis's Bite".to_string();
+        }
+        let catalog = Catalog::new(cards);
+        let first = &catalog.cards["card_1"];
+        let second = &catalog.cards["card_2"];
+        assert_eq!(catalog.slug(first), "vampire-mantiss-bite");
+        assert_eq!(catalog.slug(second), "vampire-mantiss-bite-card-2");
+        assert_eq!(
+            catalog.by_slug("Vampire-Mantiss-Bite").unwrap().id,
+            "card_1"
+        );
+        assert_eq!(
+            catalog.by_slug("vampire-mantiss-bite-card-2").unwrap().id,
+            "card_2"
+        );
+        assert!(catalog.by_slug("vampire-mantis").is_none());
+    }
+
+    #[test]
+    fn every_card_gets_a_slug_of_its_own() {
+        let cards = [("a", "Foo Y"), ("b", "Foo"), ("y", "Foo")].map(|(id, name)| {
+            let mut card = numbered(1);
+            card.id = id.to_string();
+            card.name = name.to_string();
+            (card.id.clone(), card)
+        });
+        let catalog = Catalog::new(HashMap::from(cards));
+        let slugs = ["a", "b", "y"].map(|x| catalog.slug(&catalog.cards[x]));
+        assert_eq!(slugs, ["foo-y", "foo", "foo-y-2"]);
+        for (id, slug) in ["a", "b", "y"].iter().zip(&slugs) {
+            assert_eq!(&catalog.by_slug(slug).unwrap().id, id);
+        }
+    }
+
+    #[test]
+    fn references_come_from_keywords_and_text() {
+        let mut cards = catalog(4).cards;
+        let devourer = cards.get_mut("card_2").unwrap();
+        devourer.keywords = vec![serde_json::from_value(json!({
+            "name": "devours",
+            "data": { "type": "CardID", "name": "card 1" },
+        }))
+        .unwrap()];
+        cards.get_mut("card_3").unwrap().abilities = vec!["Summon a CARD 1.".to_string()];
+        let catalog = Catalog::new(cards);
+
+        let references = catalog.referencing(&catalog.cards["card_1"]);
+        let found: Vec<(&str, &[&str], bool)> = references
+            .iter()
+            .map(|x| (x.card.id.as_str(), x.keywords.as_slice(), x.mentions))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("card_2", &["devours"][..], false),
+                ("card_3", &[][..], true)
+            ]
+        );
+        assert!(catalog.referencing(&catalog.cards["card_4"]).is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::config::Config;
+use crate::log::LogLevel;
+
+/// A search engine for Bloodless cards. Flags take precedence over environment variables, which
+/// take precedence over the configuration file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Configuration file to read instead of config.toml, also set with `CONFIG_PATH`.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Where the card catalog is read from.
+    #[arg(long, value_name = "PATH")]
+    pub cards: Option<PathBuf>,
+    #[arg(long)]
+    pub host: Option<String>,
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Where the frontend build is.
+    #[arg(long, value_name = "DIR")]
+    pub dist: Option<PathBuf>,
+    /// Send pages without rendering them first, leaving it all to the frontend.
+    #[arg(long)]
+    pub no_ssr: bool,
+    /// How much is logged: error, warn or info.
+    #[arg(long, value_name = "LEVEL")]
+    pub log_level: Option<LogLevel>,
+    /// Only load the cards and print what's wrong with them, exiting with an error if any card
+    /// failed to load. The server isn't started.
+    #[arg(long)]
+    pub check: bool,
+}
+
+impl Cli {
+    /// Overrides the settings of `config` with the flags that were given.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(path) = &self.cards {
+            config.cards_path.clone_from(path);
+        }
+        if let Some(host) = &self.host {
+            config.host.clone_from(host);
+        }
+        if let Some(port) = self.port {
+            config.port = port;
+        }
+        if let Some(dist) = &self.dist {
+            config.dist_path.clone_from(dist);
+        }
+        if self.no_ssr {
+            config.ssr = false;
+        }
+        if let Some(level) = self.log_level {
+            config.log_level = level;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_the_config() {
+        let cli = Cli::try_parse_from([
+            "hemolymph",
+            "--cards",
+            "/data/cards.json",
+            "--port",
+            "9000",
+            "--no-ssr",
+            "--log-level",
+            "error",
+        ])
+        .unwrap();
+        let mut config = Config {
+            port: 8000,
+            host: "0.0.0.0".to_string(),
+            ..Config::default()
+        };
+        cli.apply(&mut config);
+        assert_eq!(config.cards_path, PathBuf::from("/data/cards.json"));
+        assert_eq!((config.host.as_str(), config.port), ("0.0.0.0", 9000));
+        assert!(!config.ssr && !cli.check);
+        assert_eq!(config.log_level, LogLevel::Error);
+
+        assert!(Cli::try_parse_from(["hemolymph", "--port", "ninety"]).is_err());
+    }
+}
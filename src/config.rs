@@ -0,0 +1,291 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use actix_web::http::Uri;
+use serde::{Deserialize, Serialize};
+
+use crate::loader::DuplicatePolicy;
+use crate::log::LogLevel;
+
+/// Where the configuration is read from when neither `--config` nor `CONFIG_PATH` say otherwise.
+/// The file is optional there.
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+/// Most cards sent for one search when `max_results` isn't set.
+pub const DEFAULT_MAX_RESULTS: usize = 1000;
+
+/// Searches remembered when `search_cache_size` isn't set.
+pub const DEFAULT_SEARCH_CACHE_SIZE: usize = 256;
+
+/// Seconds between fetches of the cards when `cards_path` is a URL and `refresh_interval` isn't
+/// set.
+pub const DEFAULT_REFRESH_INTERVAL: u64 = 300;
+
+/// Where card images are when `image_base_url` isn't set or is empty.
+pub const DEFAULT_IMAGE_BASE_URL: &str = "https://file.garden/ZJSEzoaUL3bz8vYK/bloodlesscards";
+
+/// Settings of the server, read from `config.toml` and overridden by environment variables. Each
+/// field is also set by the variable named like it in uppercase, like `CARDS_PATH`.
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub host: String,
+    pub port: u16,
+    /// Where the card catalog is read from. Several files or directories can be given separated
+    /// by commas, and each can be a glob like `cards/*.json`. An `http://` or `https://` URL is
+    /// fetched instead, and has to be a JSON array of cards.
+    pub cards_path: PathBuf,
+    /// Seconds between fetches of the cards when `cards_path` is a URL. With 0 they're only
+    /// fetched at startup and on reloads.
+    pub refresh_interval: u64,
+    /// What to do with cards whose id an earlier card, maybe of another file, already has.
+    pub duplicate_ids: DuplicatePolicy,
+    /// Where the frontend build is.
+    pub dist_path: PathBuf,
+    /// Threads answering requests. One per CPU core when it's unset.
+    pub workers: Option<usize>,
+    /// Seconds an idle connection is kept open for more requests. With 0 connections are closed
+    /// after each response, and when it's unset it's actix-web's default of 5.
+    pub keep_alive: Option<u64>,
+    /// Where card images are.
+    pub image_base_url: String,
+    /// Most cards sent for one search. Pages are never bigger, and other searches with more
+    /// matches are cut short.
+    pub max_results: usize,
+    /// Number of searches whose results are remembered. With 0 nothing is cached.
+    pub search_cache_size: usize,
+    /// Origins allowed to call the API from a browser, like `https://example.com`, also set with
+    /// `CORS_ORIGINS` separated by commas. Any origin is allowed when it's empty or has `*`.
+    pub cors_origins: Vec<String>,
+    /// Shared secret required by `/api/reload` and the `/api/admin` endpoints, also set with
+    /// `RELOAD_TOKEN` as before. They are all disabled when it's unset. Never logged.
+    #[serde(skip_serializing)]
+    pub admin_token: Option<String>,
+    /// Whether pages are rendered before they're sent. Without it, pages are the template with
+    /// its meta tags filled in, and the frontend renders everything.
+    pub ssr: bool,
+    /// How much is logged: `error`, `warn` or `info`.
+    pub log_level: LogLevel,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            host: "127.0.0.1".to_string(),
+            port: 8080,
+            cards_path: PathBuf::from("cards.json"),
+            refresh_interval: DEFAULT_REFRESH_INTERVAL,
+            duplicate_ids: DuplicatePolicy::KeepFirst,
+            dist_path: PathBuf::from("dist"),
+            workers: None,
+            keep_alive: None,
+            image_base_url: DEFAULT_IMAGE_BASE_URL.to_string(),
+            max_results: DEFAULT_MAX_RESULTS,
+            search_cache_size: DEFAULT_SEARCH_CACHE_SIZE,
+            cors_origins: vec![],
+            admin_token: None,
+            ssr: true,
+            log_level: LogLevel::Info,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file at `path`, or at [`DEFAULT_CONFIG_PATH`] if it's nothing,
+    /// and overrides it with the variables `var` looks up. A file that was asked for must exist,
+    /// and the default one may not. Fails on files and variables that can't be read as settings,
+    /// saying which one.
+    pub fn load(path: Option<&Path>, var: impl Fn(&str) -> Option<String>) -> Result<Self, String> {
+        let file = path.unwrap_or(Path::new(DEFAULT_CONFIG_PATH));
+        let mut config = match fs::read_to_string(file) {
+            Ok(text) => toml::from_str(&text).map_err(|x| format!("{}: {x}", file.display()))?,
+            Err(x) if x.kind() == io::ErrorKind::NotFound && path.is_none() => Self::default(),
+            Err(x) => return Err(format!("Unable to read {}: {x}", file.display())),
+        };
+
+        // Empty variables count as unset, like they did before the configuration file.
+        let var = |name: &str| var(name).filter(|x| !x.trim().is_empty());
+        let parsed = |name: &str, what: &str| -> Result<Option<usize>, String> {
+            parse(name, var(name), what)
+        };
+        if let Some(host) = var("HOST") {
+            config.host = host;
+        }
+        if let Some(port) = parse("PORT", var("PORT"), "a port")? {
+            config.port = port;
+        }
+        if let Some(path) = var("CARDS_PATH") {
+            config.cards_path = PathBuf::from(path);
+        }
+        if let Some(secs) = parse(
+            "REFRESH_INTERVAL",
+            var("REFRESH_INTERVAL"),
+            "a number of seconds",
+        )? {
+            config.refresh_interval = secs;
+        }
+        if let Some(policy) = parse(
+            "DUPLICATE_IDS",
+            var("DUPLICATE_IDS"),
+            "keep_first, keep_last or reject",
+        )? {
+            config.duplicate_ids = policy;
+        }
+        if let Some(path) = var("DIST_PATH") {
+            config.dist_path = PathBuf::from(path);
+        }
+        if let Some(workers) = parsed("WORKERS", "a number of threads")? {
+            config.workers = Some(workers);
+        }
+        if let Some(secs) = parse("KEEP_ALIVE", var("KEEP_ALIVE"), "a number of seconds")? {
+            config.keep_alive = Some(secs);
+        }
+        if let Some(url) = var("IMAGE_BASE_URL") {
+            config.image_base_url = url;
+        }
+        if let Some(max) = parsed("MAX_RESULTS", "a number of cards")? {
+            config.max_results = max;
+        }
+        if let Some(size) = parsed("SEARCH_CACHE_SIZE", "a number of searches")? {
+            config.search_cache_size = size;
+        }
+        if let Some(origins) = var("CORS_ORIGINS") {
+            config.cors_origins = origins
+                .split(',')
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(str::to_string)
+                .collect();
+        }
+        if let Some(token) = var("ADMIN_TOKEN").or_else(|| var("RELOAD_TOKEN")) {
+            config.admin_token = Some(token);
+        }
+        if let Some(ssr) = parse("SSR", var("SSR"), "true or false")? {
+            config.ssr = ssr;
+        }
+        if let Some(level) = parse("LOG_LEVEL", var("LOG_LEVEL"), "error, warn or info")? {
+            config.log_level = level;
+        }
+
+        if config.max_results == 0 {
+            return Err("max_results must be at least 1".to_string());
+        }
+        if config.workers == Some(0) {
+            return Err("workers must be at least 1".to_string());
+        }
+        if config.cors_origins.iter().any(|x| x == "*") {
+            config.cors_origins.clear();
+        }
+        for origin in &config.cors_origins {
+            check_origin(origin)?;
+        }
+        if config.image_base_url.trim().is_empty() {
+            config.image_base_url = DEFAULT_IMAGE_BASE_URL.to_string();
+        }
+        if config.admin_token.as_ref().is_some_and(|x| x.is_empty()) {
+            config.admin_token = None;
+        }
+        Ok(config)
+    }
+
+    /// The configuration as it's logged at startup, without the admin token.
+    pub fn describe(&self) -> String {
+        toml::to_string(self).unwrap_or_else(|x| format!("Unable to show the configuration: {x}"))
+    }
+}
+
+/// Fails unless `origin` is a scheme and a host, with an optional port and nothing else, as
+/// browsers send it in the `Origin` header.
+fn check_origin(origin: &str) -> Result<(), String> {
+    let error =
+        || format!("The CORS origin {origin:?} isn't a scheme and a host like https://example.com");
+    let uri: Uri = origin.parse().map_err(|_| error())?;
+    match (uri.scheme(), uri.authority()) {
+        (Some(scheme), Some(authority))
+            if format!("{scheme}://{authority}").eq_ignore_ascii_case(origin) =>
+        {
+            Ok(())
+        }
+        _ => Err(error()),
+    }
+}
+
+/// Reads the variable `name`, which holds `what`, if it's set.
+fn parse<T: FromStr>(name: &str, value: Option<String>, what: &str) -> Result<Option<T>, String> {
+    value
+        .map(|x| {
+            x.trim()
+                .parse()
+                .map_err(|_| format!("{name} is {x:?}, which isn't {what}"))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn variables_override_the_file() {
+        let path = env::temp_dir().join(format!("hemolymph-config-{}.toml", std::process::id()));
+        fs::write(
+            &path,
+            "port = 9000\ncards_path = \"data/cards\"\ncors_origins = [\"https://a.example\"]\n\
+             admin_token = \"secret\"\n",
+        )
+        .unwrap();
+        let vars = HashMap::from([
+            ("PORT", "9001"),
+            ("HOST", ""),
+            ("MAX_RESULTS", "20"),
+            ("KEEP_ALIVE", "0"),
+            ("LOG_LEVEL", "Warn"),
+        ]);
+        let var = |x: &str| vars.get(x).map(|x| (*x).to_string());
+
+        let config = Config::load(Some(&path), var).unwrap();
+        assert_eq!(config.port, 9001);
+        assert_eq!(config.host, "127.0.0.1");
+        assert_eq!(config.cards_path, PathBuf::from("data/cards"));
+        assert_eq!(config.max_results, 20);
+        assert_eq!((config.workers, config.keep_alive), (None, Some(0)));
+        assert_eq!(config.log_level, LogLevel::Warn);
+        assert_eq!(config.cors_origins, ["https://a.example"]);
+        assert_eq!(config.admin_token.as_deref(), Some("secret"));
+        assert!(!config.describe().contains("secret"));
+
+        let missing = Path::new("/nonexistent/hemolymph.toml");
+        assert!(Config::load(Some(missing), |_| None).is_err());
+        assert_eq!(
+            Config::load(Some(&path), |x| (x == "PORT").then(|| "eighty".to_string())).unwrap_err(),
+            "PORT is \"eighty\", which isn't a port"
+        );
+        assert!(Config::load(Some(&path), |x| (x == "WORKERS").then(|| "0".to_string())).is_err());
+        let origins = |origins: &'static str| {
+            Config::load(Some(&path), move |x| {
+                (x == "CORS_ORIGINS").then(|| origins.to_string())
+            })
+        };
+        assert!(origins("https://a.example, *")
+            .unwrap()
+            .cors_origins
+            .is_empty());
+        assert_eq!(
+            origins("http://localhost:8080").unwrap().cors_origins,
+            ["http://localhost:8080"]
+        );
+        for origin in ["a.example", "https://a.example/path", "https://a example"] {
+            assert!(origins(origin).unwrap_err().contains(origin), "{origin}");
+        }
+        fs::write(&path, "prot = 9000\n").unwrap();
+        assert!(Config::load(Some(&path), |_| None)
+            .unwrap_err()
+            .contains("prot"));
+        fs::remove_file(&path).unwrap();
+    }
+}
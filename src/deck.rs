@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::cards::{Card, CardType, Legality};
+use crate::catalog::Catalog;
+
+/// A card of a deck and how many copies of it the deck has.
+#[derive(Serialize)]
+pub struct DeckEntry<'a> {
+    pub quantity: usize,
+    pub card: &'a Card,
+}
+
+/// A decklist matched against the catalog.
+#[derive(Serialize)]
+pub struct Deck<'a> {
+    /// Cards in the order they first appear in the list. Repeated lines are merged.
+    pub cards: Vec<DeckEntry<'a>>,
+    /// Lines that don't name any card.
+    pub unresolved: Vec<String>,
+    /// Number of cards in the deck, counting every copy.
+    pub total: usize,
+    /// Number of cards of each type, counting every copy, in the order types are listed in.
+    pub types: BTreeMap<CardType, usize>,
+}
+
+/// Splits a decklist line like `3 Card Name` or `3x Card Name` into its quantity and name. Lines
+/// without a quantity stand for a single copy.
+pub fn parse_line(line: &str) -> (usize, &str) {
+    let line = line.trim();
+    let Some((quantity, name)) = line.split_once(char::is_whitespace) else {
+        return (1, line);
+    };
+    let quantity = quantity
+        .strip_suffix(['x', 'X'])
+        .unwrap_or(quantity)
+        .parse::<usize>();
+    match quantity {
+        Ok(quantity) => (quantity, name.trim()),
+        Err(_) => (1, line),
+    }
+}
+
+/// Resolves every line of `text` to a card by name. Blank lines are skipped.
+pub fn resolve<'a>(text: &str, catalog: &'a Catalog) -> Deck<'a> {
+    let mut deck = Deck {
+        cards: vec![],
+        unresolved: vec![],
+        total: 0,
+        types: BTreeMap::new(),
+    };
+
+    for line in text.lines().filter(|x| !x.trim().is_empty()) {
+        let (quantity, name) = parse_line(line);
+        let Some(card) = catalog.by_name(name) else {
+            deck.unresolved.push(line.trim().to_string());
+            continue;
+        };
+
+        match deck.cards.iter_mut().find(|x| x.card.id == card.id) {
+            Some(entry) => entry.quantity += quantity,
+            None => deck.cards.push(DeckEntry { quantity, card }),
+        }
+        deck.total += quantity;
+        *deck.types.entry(card.card_type()).or_default() += quantity;
+    }
+
+    deck
+}
+
+/// A card that can't be in a deck of some format.
+#[derive(Serialize)]
+pub struct IllegalCard<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    /// The card's status in the format, or nothing if the card doesn't list the format at all.
+    pub legality: Option<&'a Legality>,
+}
+
+/// A card that's allowed in a format, but not with this many copies.
+#[derive(Serialize)]
+pub struct OverLimit<'a> {
+    pub id: &'a str,
+    pub name: &'a str,
+    pub quantity: usize,
+    pub limit: usize,
+}
+
+/// Whether a deck can be played in a format, and why not.
+#[derive(Serialize)]
+pub struct LegalityReport<'a> {
+    pub format: String,
+    pub legal: bool,
+    pub illegal: Vec<IllegalCard<'a>>,
+    pub over_limit: Vec<OverLimit<'a>>,
+    /// Lines that didn't name a card. A deck with any of them isn't considered legal.
+    pub unresolved: Vec<String>,
+}
+
+/// Most copies of a restricted card a deck can have.
+const RESTRICTED_LIMIT: usize = 1;
+
+/// Checks every card of the deck against its legality in `format`, matched ignoring case.
+pub fn check_legality<'a>(deck: Deck<'a>, format: &str) -> LegalityReport<'a> {
+    let mut illegal = vec![];
+    let mut over_limit = vec![];
+
+    for entry in &deck.cards {
+        let card = entry.card;
+        let legality = card
+            .legality
+            .iter()
+            .find(|(x, _)| x.eq_ignore_ascii_case(format))
+            .map(|(_, x)| &x.status);
+
+        match legality {
+            Some(Legality::Restricted) if entry.quantity > RESTRICTED_LIMIT => {
+                over_limit.push(OverLimit {
+                    id: &card.id,
+                    name: &card.name,
+                    quantity: entry.quantity,
+                    limit: RESTRICTED_LIMIT,
+                });
+            }
+            Some(x) if x.is_playable() => (),
+            legality => illegal.push(IllegalCard {
+                id: &card.id,
+                name: &card.name,
+                legality,
+            }),
+        }
+    }
+
+    LegalityReport {
+        format: format.to_string(),
+        legal: illegal.is_empty() && over_limit.is_empty() && deck.unresolved.is_empty(),
+        illegal,
+        over_limit,
+        unresolved: deck.unresolved,
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the optional file, next to the card file, that maps each kin to the broader kin it
+/// belongs to.
+const HIERARCHY_FILE: &str = "kin_hierarchy.json";
+
+/// Which kin each kin belongs to. Every kin is stored lowercase.
+#[derive(Default)]
+pub struct KinHierarchy {
+    parents: HashMap<String, String>,
+}
+
+impl KinHierarchy {
+    /// Where the hierarchy for the card file at `cards_path` is read from.
+    pub fn path_for(cards_path: &Path) -> PathBuf {
+        cards_path.with_file_name(HIERARCHY_FILE)
+    }
+
+    /// Reads a JSON map from child kin to parent kin. A missing file is an empty hierarchy, and
+    /// an unreadable one is reported and treated as empty.
+    pub fn load(path: &Path) -> (Self, Vec<String>) {
+        let links = match fs::read_to_string(path) {
+            Ok(data) => serde_json::from_str(&data).map_err(io::Error::from),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(error) => Err(error),
+        };
+        match links {
+            Ok(links) => Self::from_links(links),
+            Err(error) => (
+                Self::default(),
+                vec![format!("Unable to load {}: {error}", path.display())],
+            ),
+        }
+    }
+
+    /// Builds the hierarchy from child to parent links. Links that would make a kin belong to
+    /// itself are left out and reported, so the hierarchy never has cycles.
+    pub fn from_links(links: HashMap<String, String>) -> (Self, Vec<String>) {
+        let mut links: Vec<(String, String)> = links
+            .into_iter()
+            .map(|(child, parent)| (child.trim().to_lowercase(), parent.trim().to_lowercase()))
+            .collect();
+        links.sort_unstable();
+
+        let mut hierarchy = Self::default();
+        let mut warnings = vec![];
+        for (child, parent) in links {
+            if hierarchy.is_within(&parent, &child) {
+                warnings.push(format!(
+                    "Kin {child:?} can't belong to {parent:?}, since {parent:?} already belongs \
+                     to {child:?}"
+                ));
+            } else {
+                hierarchy.parents.insert(child, parent);
+            }
+        }
+        (hierarchy, warnings)
+    }
+
+    pub fn parent(&self, kin: &str) -> Option<&str> {
+        self.parents.get(kin).map(String::as_str)
+    }
+
+    /// Every kin the hierarchy mentions, as a child or as a parent.
+    pub fn kins(&self) -> impl Iterator<Item = &str> {
+        self.parents
+            .iter()
+            .flat_map(|(child, parent)| [child.as_str(), parent.as_str()])
+    }
+
+    /// The parent of `kin`, the parent of that parent and so on.
+    pub fn ancestors<'a>(&'a self, kin: &str) -> Vec<&'a str> {
+        let mut ancestors = vec![];
+        let mut current = kin;
+        while let Some(parent) = self.parents.get(current) {
+            ancestors.push(parent.as_str());
+            current = parent;
+        }
+        ancestors
+    }
+
+    /// Whether `kin` is `ancestor` or descends from it. Both must be lowercase.
+    pub fn is_within(&self, kin: &str, ancestor: &str) -> bool {
+        kin == ancestor || self.ancestors(kin).contains(&ancestor)
+    }
+
+    /// `kin` in lowercase together with every kin that descends from it, sorted.
+    pub fn with_descendants(&self, kin: &str) -> Vec<String> {
+        let kin = kin.trim().to_lowercase();
+        let mut kins: Vec<String> = self
+            .parents
+            .keys()
+            .filter(|x| self.is_within(x, &kin))
+            .cloned()
+            .collect();
+        kins.push(kin);
+        kins.sort_unstable();
+        kins.dedup();
+        kins
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hierarchy(links: &[(&str, &str)]) -> (KinHierarchy, Vec<String>) {
+        KinHierarchy::from_links(
+            links
+                .iter()
+                .map(|(child, parent)| (child.to_string(), parent.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parents_include_their_descendants() {
+        let (kins, warnings) = hierarchy(&[("mantis", "Insect"), ("insect", "bug")]);
+        assert!(warnings.is_empty());
+        assert_eq!(kins.ancestors("mantis"), ["insect", "bug"]);
+        assert_eq!(kins.with_descendants("Bug"), ["bug", "insect", "mantis"]);
+        assert!(!kins.is_within("bug", "mantis"));
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let (kins, warnings) = hierarchy(&[("a", "b"), ("b", "c"), ("c", "a"), ("d", "d")]);
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(kins.ancestors("a"), ["b", "c"]);
+        assert!(kins.parent("d").is_none());
+    }
+}
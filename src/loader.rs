@@ -0,0 +1,696 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::ffi::OsStr;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cards::{Card, CardType, Legality, CARD_FIELDS};
+use crate::catalog::Catalog;
+use crate::kins::KinHierarchy;
+use crate::log;
+
+/// Name of the optional file, next to the cards, that maps each set to its release order.
+const SETS_FILE: &str = "sets.json";
+
+/// A card of the catalog that couldn't be read.
+#[derive(Serialize, Clone)]
+pub struct CardError {
+    /// Position of the card in the file.
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The file the card is in, when cards are read from a directory.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// Where in the card the problem is, like `keywords[0].data`.
+    pub path: String,
+    pub message: String,
+}
+
+impl Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Card #{}", self.index)?;
+        if let Some(file) = &self.file {
+            write!(f, " of {file}")?;
+        }
+        if let Some(id) = &self.id {
+            write!(f, " ({id})")?;
+        }
+        write!(f, " at {}: {}", self.path, self.message)
+    }
+}
+
+/// Why the catalog couldn't be loaded at all. Problems with single cards are [`CardError`]s
+/// instead, and don't stop the rest from loading.
+#[derive(Debug)]
+pub enum CardLoadError {
+    /// The file, or a directory of card files, couldn't be read.
+    Io(PathBuf, io::Error),
+    /// The file isn't a JSON array.
+    Parse(PathBuf, serde_json::Error),
+    /// There isn't a single card, which is more likely a mistake than a catalog.
+    Empty(PathBuf),
+    /// More than one card has this id.
+    DuplicateId(String),
+}
+
+impl CardLoadError {
+    /// Whether the catalog isn't there at all, which may only mean it hasn't been written yet.
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, CardLoadError::Io(_, x) if x.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl Display for CardLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CardLoadError::Io(path, error) => {
+                write!(f, "Unable to read {}: {error}", path.display())
+            }
+            CardLoadError::Parse(path, error) => {
+                write!(f, "{} isn't an array of cards: {error}", path.display())
+            }
+            CardLoadError::Empty(path) => write!(f, "{} has no cards", path.display()),
+            CardLoadError::DuplicateId(id) => write!(f, "More than one card has the id {id}"),
+        }
+    }
+}
+
+impl Error for CardLoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CardLoadError::Io(_, error) => Some(error),
+            CardLoadError::Parse(_, error) => Some(error),
+            CardLoadError::Empty(_) | CardLoadError::DuplicateId(_) => None,
+        }
+    }
+}
+
+/// What happened while loading the catalog.
+#[derive(Serialize, Clone, Default)]
+pub struct LoadReport {
+    /// Number of cards in the file, including the ones that failed.
+    pub total: usize,
+    pub loaded: usize,
+    pub errors: Vec<CardError>,
+    /// Problems that didn't prevent cards from loading.
+    pub warnings: Vec<String>,
+}
+
+impl LoadReport {
+    /// Fraction of the cards in the file that couldn't be read.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn invalid_fraction(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.errors.len() as f64 / self.total as f64
+        }
+    }
+
+    pub fn log(&self) {
+        for error in &self.errors {
+            log::error!("Error: {error}");
+        }
+        for warning in &self.warnings {
+            log::warn!("Warning: {warning}");
+        }
+    }
+}
+
+/// What to do with cards that have fields no card can have, which usually are typos. Set with
+/// `STRICT_CARDS=warn|deny`.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// Unknown fields are ignored.
+    #[default]
+    Off,
+    /// Unknown fields are reported as warnings.
+    Warn,
+    /// Cards with unknown fields are left out and reported as errors.
+    Deny,
+}
+
+impl Strictness {
+    pub fn from_env(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            Some(x) if x.eq_ignore_ascii_case("warn") => Strictness::Warn,
+            Some(x) if x.eq_ignore_ascii_case("deny") => Strictness::Deny,
+            _ => Strictness::Off,
+        }
+    }
+}
+
+/// What to do with a card whose id an earlier card already has, like when the same card is in
+/// two files. Set with `duplicate_ids` or `DUPLICATE_IDS`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DuplicatePolicy {
+    /// The first card is kept, and later ones are left out and reported as errors.
+    #[default]
+    KeepFirst,
+    /// Later cards replace earlier ones, and every replacement is reported as a warning.
+    KeepLast,
+    /// The whole catalog fails to load, so a reload keeps the previous one.
+    Reject,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "keep_first" => Ok(DuplicatePolicy::KeepFirst),
+            "keep_last" => Ok(DuplicatePolicy::KeepLast),
+            "reject" => Ok(DuplicatePolicy::Reject),
+            x => Err(format!("Unknown policy for repeated ids: {x}")),
+        }
+    }
+}
+
+/// The cards last read from each of several card sources, so one that can't be read on a reload
+/// keeps its previous cards instead of taking them out of the catalog.
+#[derive(Default)]
+pub struct SourceCache {
+    entries: HashMap<PathBuf, Vec<Entry>>,
+}
+
+/// The paths `spec` names: one path, or several separated by commas, each of which can be a glob
+/// like `cards/*.json` that names every file it matches in order. A path that exists is never
+/// split.
+pub fn card_paths(spec: &Path) -> Vec<PathBuf> {
+    if spec.exists() {
+        return vec![spec.to_path_buf()];
+    }
+    spec.to_string_lossy()
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .flat_map(|part| {
+            if !is_glob(part) {
+                return vec![PathBuf::from(part)];
+            }
+            match glob::glob(part) {
+                Ok(paths) => paths.filter_map(Result::ok).collect(),
+                // Not a valid pattern, so it's taken as a path, which will fail to load.
+                Err(_) => vec![PathBuf::from(part)],
+            }
+        })
+        .collect()
+}
+
+/// Where to watch for changes to the cards of `spec`: every path it names, and for globs, the
+/// folder before the first wildcard, where new matching files would appear.
+pub fn watched_paths(spec: &Path) -> Vec<PathBuf> {
+    if spec.exists() {
+        return vec![spec.to_path_buf()];
+    }
+    spec.to_string_lossy()
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(|part| {
+            if !is_glob(part) {
+                return PathBuf::from(part);
+            }
+            let root: PathBuf = Path::new(part)
+                .components()
+                .take_while(|x| !is_glob(&x.as_os_str().to_string_lossy()))
+                .collect();
+            if root.as_os_str().is_empty() || root.components().all(|x| x == Component::CurDir) {
+                PathBuf::from(".")
+            } else {
+                root
+            }
+        })
+        .collect()
+}
+
+/// Where the kin hierarchy of the cards of `spec` is: next to the first path it names.
+pub fn kins_path(spec: &Path) -> PathBuf {
+    KinHierarchy::path_for(card_paths(spec).first().map_or(spec, PathBuf::as_path))
+}
+
+/// Where the release order of the sets of the cards of `spec` is: next to the first path it
+/// names, like the kin hierarchy.
+pub fn sets_path(spec: &Path) -> PathBuf {
+    card_paths(spec)
+        .first()
+        .map_or(spec, PathBuf::as_path)
+        .with_file_name(SETS_FILE)
+}
+
+/// Reads a JSON map from set name to its release order. A missing file is an empty order.
+fn load_set_order(path: &Path) -> Result<HashMap<String, usize>, String> {
+    match fs::read_to_string(path) {
+        Ok(data) => serde_json::from_str(&data)
+            .map_err(|x| format!("Unable to load {}: {x}", path.display())),
+        Err(x) if x.kind() == io::ErrorKind::NotFound => Ok(HashMap::new()),
+        Err(x) => Err(format!("Unable to load {}: {x}", path.display())),
+    }
+}
+
+fn is_glob(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// Maps every card to its id. When ids repeat, the first card with the id is kept and the rest
+/// are left out, like [`load_cards`] does with [`DuplicatePolicy::KeepFirst`].
+/// [`create_card_map_checked`] fails instead.
+pub fn create_card_map(vec: Vec<Card>) -> HashMap<String, Card> {
+    let mut cards = HashMap::with_capacity(vec.len());
+    for card in vec {
+        cards.entry(card.id.clone()).or_insert(card);
+    }
+    cards
+}
+
+/// Maps every card to its id, failing with the first id that repeats instead of leaving cards
+/// out.
+pub fn create_card_map_checked(vec: Vec<Card>) -> Result<HashMap<String, Card>, CardLoadError> {
+    let mut ids = HashSet::new();
+    if let Some(card) = vec.iter().find(|x| !ids.insert(x.id.as_str())) {
+        return Err(CardLoadError::DuplicateId(card.id.clone()));
+    }
+    Ok(create_card_map(vec))
+}
+
+/// A card read from a file, before it's checked.
+#[derive(Clone)]
+struct Entry {
+    /// The file the card is in, when cards come from a directory.
+    file: Option<String>,
+    /// Position of the card in its file.
+    index: usize,
+    value: Value,
+}
+
+/// Reads the cards of every path `spec` names with [`card_paths`], merged in order, and the kin
+/// hierarchy next to the first of them. Used both at startup and whenever the catalog is
+/// reloaded, so every path applies the same validation.
+///
+/// Each path is either a JSON file with an array of cards, or a directory where every `.json`,
+/// `.toml`, `.yaml` and `.yml` file, at any depth, holds one card or an array of them. TOML files
+/// can't be arrays, so they hold one card or a `cards` array.
+///
+/// Cards are read one by one, so a malformed card is left out and reported instead of failing
+/// the whole catalog. Cards that repeat an id, whether in the same file or another one, are
+/// handled as `duplicates` says. Only an unreadable file, one that isn't a JSON array, or a
+/// catalog without any card is an error. When there are several paths, one that can't be read
+/// uses its cards from the last time it was read into `cache`, if there's one. In a directory,
+/// files that can't be read are reported and skipped.
+pub fn load_cards(
+    spec: &Path,
+    strictness: Strictness,
+    duplicates: DuplicatePolicy,
+    cache: &mut SourceCache,
+) -> Result<(Catalog, LoadReport), CardLoadError> {
+    let mut report = LoadReport::default();
+    let mut entries = vec![];
+    let paths = card_paths(spec);
+    let several = paths.len() > 1;
+    for path in &paths {
+        match read_source(path, several, &mut report) {
+            Ok(read) => {
+                if several {
+                    cache.entries.insert(path.clone(), read.clone());
+                }
+                entries.extend(read);
+            }
+            Err(error) => match cache.entries.get(path) {
+                Some(previous) if several => {
+                    report
+                        .warnings
+                        .push(format!("{error}, so its cards from the last read are kept"));
+                    entries.extend(previous.iter().cloned());
+                }
+                _ => return Err(error),
+            },
+        }
+    }
+    build_catalog(spec, entries, report, strictness, duplicates, true)
+}
+
+/// Reads the cards of `data`, a JSON array of cards fetched from `url`, like [`load_cards`]
+/// reads a file. There's no kin hierarchy next to fetched cards, so it's empty.
+pub fn parse_cards(
+    url: &str,
+    data: &str,
+    strictness: Strictness,
+    duplicates: DuplicatePolicy,
+) -> Result<(Catalog, LoadReport), CardLoadError> {
+    let values: Vec<Value> =
+        serde_json::from_str(data).map_err(|x| CardLoadError::Parse(PathBuf::from(url), x))?;
+    let entries = values
+        .into_iter()
+        .enumerate()
+        .map(|(index, value)| Entry {
+            file: None,
+            index,
+            value,
+        })
+        .collect();
+    let report = LoadReport::default();
+    build_catalog(
+        Path::new(url),
+        entries,
+        report,
+        strictness,
+        duplicates,
+        false,
+    )
+}
+
+/// Checks the cards read from `spec` and builds their catalog, with the kin hierarchy and set
+/// order next to them if `neighbors` is set. Either of them failing to load is a warning.
+fn build_catalog(
+    spec: &Path,
+    entries: Vec<Entry>,
+    mut report: LoadReport,
+    strictness: Strictness,
+    duplicates: DuplicatePolicy,
+    neighbors: bool,
+) -> Result<(Catalog, LoadReport), CardLoadError> {
+    report.total += entries.len();
+    if report.total == 0 {
+        return Err(CardLoadError::Empty(spec.to_path_buf()));
+    }
+    let mut cards: Vec<Card> = vec![];
+    // Where the card kept for each id was seen, and its position in `cards`, to report repeated
+    // ones.
+    let mut sources: HashMap<String, (Option<String>, usize, usize)> = HashMap::new();
+    for Entry { file, index, value } in entries {
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string);
+        let (id, name) = (field("id"), field("name"));
+
+        let unknown_fields: Vec<&str> = value
+            .as_object()
+            .map(|x| {
+                x.keys()
+                    .map(String::as_str)
+                    .filter(|key| !CARD_FIELDS.contains(key))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if !unknown_fields.is_empty() {
+            let label = id
+                .as_deref()
+                .map_or_else(|| format!("#{index}"), str::to_string);
+            match strictness {
+                Strictness::Off => (),
+                Strictness::Warn => {
+                    for field in unknown_fields {
+                        report
+                            .warnings
+                            .push(format!("Card {label} has unknown field {field:?}"));
+                    }
+                }
+                Strictness::Deny => {
+                    report.errors.push(CardError {
+                        index,
+                        path: unknown_fields[0].to_string(),
+                        message: format!("Unknown fields: {}", unknown_fields.join(", ")),
+                        id,
+                        name,
+                        file,
+                    });
+                    continue;
+                }
+            }
+        }
+
+        match serde_path_to_error::deserialize::<_, Card>(value) {
+            Ok(card) => match sources.get_mut(&card.id) {
+                Some((first_file, first_index, position)) => {
+                    let first = match first_file {
+                        Some(first_file) => format!("#{first_index} in {first_file}"),
+                        None => format!("#{first_index}"),
+                    };
+                    match duplicates {
+                        DuplicatePolicy::KeepFirst => report.errors.push(CardError {
+                            index,
+                            path: "id".to_string(),
+                            message: format!("The id is already used by card {first}"),
+                            id,
+                            name,
+                            file,
+                        }),
+                        DuplicatePolicy::KeepLast => {
+                            let this = match &file {
+                                Some(file) => format!("#{index} in {file}"),
+                                None => format!("#{index}"),
+                            };
+                            report.warnings.push(format!(
+                                "Card {this} replaces card {first}, which has the same id {}",
+                                card.id
+                            ));
+                            (*first_file, *first_index) = (file, index);
+                            cards[*position] = card;
+                        }
+                        DuplicatePolicy::Reject => return Err(CardLoadError::DuplicateId(card.id)),
+                    }
+                }
+                None => {
+                    sources.insert(card.id.clone(), (file, index, cards.len()));
+                    cards.push(card);
+                }
+            },
+            Err(error) => report.errors.push(CardError {
+                index,
+                id,
+                name,
+                file,
+                path: error.path().to_string(),
+                message: error.inner().to_string(),
+            }),
+        }
+    }
+
+    // Cards that repeat an id were already left out and reported, so this only fails if that
+    // stops working, instead of silently losing cards.
+    let cards = create_card_map_checked(cards)?;
+    report.loaded = cards.len();
+    report.warnings.extend(validation_warnings(&cards));
+    let mut catalog = Catalog::new(cards);
+    report
+        .warnings
+        .extend(catalog.id_collisions.iter().cloned());
+    if neighbors {
+        let (kins, kin_warnings) = KinHierarchy::load(&kins_path(spec));
+        catalog.kins = kins;
+        report.warnings.extend(kin_warnings);
+        match load_set_order(&sets_path(spec)) {
+            Ok(order) => catalog.set_order = order,
+            Err(x) => report.warnings.push(x),
+        }
+    }
+    Ok((catalog, report))
+}
+
+/// Reads the cards of one path. `named` gives every card the file it's in, for when there are
+/// several paths.
+fn read_source(
+    path: &Path,
+    named: bool,
+    report: &mut LoadReport,
+) -> Result<Vec<Entry>, CardLoadError> {
+    let mut entries = vec![];
+    if path.is_dir() {
+        let mut files = vec![];
+        card_files(path, &mut files).map_err(|x| CardLoadError::Io(path.to_path_buf(), x))?;
+        files.sort();
+        for file in files {
+            let name = file.display().to_string();
+            match read_card_file(&file) {
+                Ok(values) => {
+                    entries.extend(values.into_iter().enumerate().map(|(index, value)| Entry {
+                        file: Some(name.clone()),
+                        index,
+                        value,
+                    }));
+                }
+                Err(message) => {
+                    report.total += 1;
+                    report.errors.push(CardError {
+                        index: 0,
+                        id: None,
+                        name: None,
+                        file: Some(name),
+                        path: String::new(),
+                        message,
+                    });
+                }
+            }
+        }
+    } else {
+        let data =
+            fs::read_to_string(path).map_err(|x| CardLoadError::Io(path.to_path_buf(), x))?;
+        let values: Vec<Value> =
+            serde_json::from_str(&data).map_err(|x| CardLoadError::Parse(path.to_path_buf(), x))?;
+        let file = named.then(|| path.display().to_string());
+        entries.extend(values.into_iter().enumerate().map(|(index, value)| Entry {
+            file: file.clone(),
+            index,
+            value,
+        }));
+    }
+    Ok(entries)
+}
+
+/// Collects every card file in `dir` and its subdirectories.
+fn card_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            card_files(&path, files)?;
+        } else if path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|x| matches!(x, "json" | "toml" | "yaml" | "yml"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Reads the cards of one file of a card directory, in the format its extension says.
+fn read_card_file(file: &Path) -> Result<Vec<Value>, String> {
+    let data = fs::read_to_string(file).map_err(|x| x.to_string())?;
+    let value: Value = match file.extension().and_then(OsStr::to_str) {
+        Some("toml") => toml::from_str(&data).map_err(|x| x.to_string())?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&data).map_err(|x| x.to_string())?,
+        _ => serde_json::from_str(&data).map_err(|x| x.to_string())?,
+    };
+    Ok(match value {
+        Value::Array(cards) => cards,
+        Value::Object(mut card) if !card.contains_key("id") && card.contains_key("cards") => {
+            match card.remove("cards") {
+                Some(Value::Array(cards)) => cards,
+                _ => return Err("cards must be an array of cards".to_string()),
+            }
+        }
+        card => vec![card],
+    })
+}
+
+/// Problems in the catalog that don't prevent it from loading.
+fn validation_warnings(cards: &HashMap<String, Card>) -> Vec<String> {
+    let mut warnings = vec![];
+
+    let mut collector_numbers: HashMap<(&str, &str), Vec<&str>> = HashMap::new();
+    for card in cards.values().filter(|x| !x.collector_number.is_empty()) {
+        collector_numbers
+            .entry((card.set.as_str(), card.collector_number.as_str()))
+            .or_default()
+            .push(card.id.as_str());
+    }
+    for ((set, number), mut ids) in collector_numbers {
+        if ids.len() > 1 {
+            ids.sort_unstable();
+            warnings.push(format!(
+                "Cards {} share collector number {number} in {set}",
+                ids.join(", ")
+            ));
+        }
+    }
+
+    let mut unknown_legalities: Vec<_> = cards
+        .values()
+        .flat_map(|card| {
+            card.legality
+                .iter()
+                .filter_map(|(format, legality)| match &legality.status {
+                    Legality::Unknown(x) => Some((&card.id, format, x)),
+                    _ => None,
+                })
+        })
+        .collect();
+    unknown_legalities.sort_unstable();
+    for (id, format, value) in unknown_legalities {
+        warnings.push(format!(
+            "Card {id} has unknown legality {value:?} in {format}"
+        ));
+    }
+
+    let mut unknown_types: Vec<_> = cards
+        .values()
+        .filter(|card| matches!(card.card_type(), CardType::Other(_)))
+        .map(|card| (&card.id, &card.r#type))
+        .collect();
+    unknown_types.sort_unstable();
+    for (id, kind) in unknown_types {
+        warnings.push(format!("Card {id} has unknown type {kind:?}"));
+    }
+
+    let mut invalid_dates: Vec<_> = cards
+        .values()
+        .filter_map(|card| {
+            let date = card.released.as_deref()?;
+            card.release_date().is_none().then_some((&card.id, date))
+        })
+        .collect();
+    invalid_dates.sort_unstable();
+    for (id, date) in invalid_dates {
+        warnings.push(format!(
+            "Card {id} has release date {date:?}, which isn't a YYYY-MM-DD date"
+        ));
+    }
+
+    warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_cards::card_with;
+
+    fn card(id: &str, name: &str) -> Card {
+        card_with(id, json!({ "name": name }))
+    }
+
+    #[test]
+    fn card_maps_keep_the_first_of_each_id() {
+        assert!(create_card_map(vec![]).is_empty());
+        assert!(create_card_map_checked(vec![]).unwrap().is_empty());
+
+        let first = card("a", "First");
+        let cards = create_card_map(vec![first.clone(), card("b", "B"), card("a", "Second")]);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&cards["a"]).unwrap(),
+            serde_json::to_value(&first).unwrap()
+        );
+
+        let error = create_card_map_checked(vec![card("a", "First"), card("a", "Second")]);
+        assert!(matches!(error, Err(CardLoadError::DuplicateId(x)) if x == "a"));
+        assert_eq!(create_card_map_checked(vec![first]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn shipped_cards_have_no_unknown_fields() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("cards.json");
+        let (catalog, report) = load_cards(
+            &path,
+            Strictness::Deny,
+            DuplicatePolicy::Reject,
+            &mut SourceCache::default(),
+        )
+        .unwrap();
+        if let Some(error) = report.errors.first() {
+            panic!("{} cards failed to load, like {error}", report.errors.len());
+        }
+        assert_eq!(catalog.cards.len(), report.total);
+        assert!(catalog.cards.values().any(|x| x.flavor_text.is_some()));
+    }
+}
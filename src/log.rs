@@ -0,0 +1,104 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use serde::{Deserialize, Serialize};
+
+/// How much the server writes to stderr, from only errors to everything it does. Set with
+/// `log_level`, `LOG_LEVEL` or `--log-level`.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    /// Only what failed, like reloads that kept the previous catalog and pages that couldn't be
+    /// rendered.
+    Error,
+    /// Also what may be wrong but doesn't stop the server, like cards with unknown fields.
+    Warn,
+    /// Also what the server does, like reloads and shutdowns.
+    #[default]
+    Info,
+}
+
+impl LogLevel {
+    /// Whether messages of `level` are written when this is the log level.
+    pub fn allows(self, level: LogLevel) -> bool {
+        level <= self
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, String> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "error" => Ok(LogLevel::Error),
+            "warn" | "warning" => Ok(LogLevel::Warn),
+            "info" => Ok(LogLevel::Info),
+            x => Err(format!("Unknown log level: {x}")),
+        }
+    }
+}
+
+/// The [`LogLevel`] the macros of this module write at, as its index.
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Makes the macros of this module write only messages `level` allows.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages of `level` are written.
+pub fn enabled(level: LogLevel) -> bool {
+    let current = match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Error,
+        1 => LogLevel::Warn,
+        _ => LogLevel::Info,
+    };
+    current.allows(level)
+}
+
+/// Writes a line to stderr like `eprintln!` does, if messages of `$level` are written.
+macro_rules! log {
+    ($level:expr, $($arg:tt)*) => {
+        if $crate::log::enabled($level) {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Writes a line about something that failed.
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::log!($crate::log::LogLevel::Error, $($arg)*)
+    };
+}
+
+/// Writes a line about something that may be wrong.
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::log::log!($crate::log::LogLevel::Warn, $($arg)*)
+    };
+}
+
+/// Writes a line about what the server does.
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::log!($crate::log::LogLevel::Info, $($arg)*)
+    };
+}
+
+pub(crate) use {error, info, log, warn};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_allow_what_is_more_severe() {
+        assert_eq!("WARNING".parse(), Ok(LogLevel::Warn));
+        assert!("debug".parse::<LogLevel>().is_err());
+        assert!(LogLevel::Warn.allows(LogLevel::Error));
+        assert!(LogLevel::Warn.allows(LogLevel::Warn));
+        assert!(!LogLevel::Warn.allows(LogLevel::Info));
+        assert!(!LogLevel::Error.allows(LogLevel::Warn));
+    }
+}
@@ -1,31 +1,366 @@
 #![warn(clippy::pedantic)]
 
+mod cache;
+mod cards;
+mod catalog;
+mod cli;
+mod config;
+mod deck;
+mod kins;
+mod loader;
+mod log;
+mod remote;
+mod render;
+mod search;
+#[cfg(test)]
+pub(crate) mod test_cards;
+
 use actix_cors::Cors;
-use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use hemoglobin::cards::Card;
-use hemoglobin::search::query_parser::query_parser;
+use actix_files::NamedFile;
+use actix_web::dev::ServerHandle;
+use actix_web::error::{InternalError, QueryPayloadError};
+use actix_web::http::{header, KeepAlive};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpResponseBuilder, HttpServer, Responder};
+use cache::{CacheKey, CacheStats, CachedSearch, ResultCache};
+use cards::{fold, is_iso_date, selected_fields, Card, KeywordData, Projected, Stat};
+use catalog::{Catalog, CatalogDiff};
+use clap::Parser;
+use cli::Cli;
+use config::Config;
 use hemolymph_frontend::ServerAppProps;
+use loader::{
+    load_cards, parse_cards, watched_paths, CardError, CardLoadError, DuplicatePolicy, LoadReport,
+    SourceCache, Strictness,
+};
 use notify::RecursiveMode;
 use notify_debouncer_mini::new_debouncer;
+use percent_encoding::percent_decode_str;
+use remote::Remote;
+use render::RenderPool;
+use search::{Errors, Highlights, Query, QueryParams, SortOrder, SynergyMatch, Weights};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
-use std::sync::mpsc::TryRecvError;
-use std::sync::Arc;
-use std::time::Duration;
-use std::{env, fs, io};
-use tokio::sync::RwLock;
-use tokio::task::{spawn_blocking, LocalSet};
+use std::borrow::Cow;
+use std::cmp::{Ordering, Reverse};
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::hash::{BuildHasher, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, io, thread};
+use subtle::ConstantTimeEq;
+use tokio::sync::{Mutex, RwLock};
+use tokio::task::spawn_blocking;
 use tokio::time::sleep;
 use yew::ServerRenderer;
 
-#[derive(Deserialize)]
-struct QueryParams {
-    query: Option<String>,
+struct AppState {
+    /// Replaced as a whole on reload. Read it through [`AppState::catalog`].
+    catalog: RwLock<Arc<Catalog>>,
+    /// The settings of `config.toml` and the environment.
+    config: Config,
+    /// Outcome of the latest attempt to load the catalog.
+    load_report: RwLock<LoadReport>,
+    /// Reloads where a larger fraction of the cards fail to parse keep the previous catalog. Set
+    /// with `MAX_INVALID_FRACTION`.
+    max_invalid_fraction: f64,
+    strictness: Strictness,
+    /// What changed in the latest reloads that replaced the catalog, oldest first. Only the last
+    /// [`RELOAD_HISTORY`] are kept.
+    reloads: RwLock<VecDeque<ReloadSummary>>,
+    /// How the latest reload went, even when it kept the previous catalog. Nothing until the
+    /// first reload.
+    last_attempt: RwLock<Option<ReloadAttempt>>,
+    /// The page template, read again whenever dist/index.html changes. Nothing while the
+    /// frontend isn't built.
+    template: RwLock<Option<Arc<PageTemplate>>>,
+    /// Whether a catalog has been loaded. It's false when the server started before the card
+    /// file existed, until the file appears.
+    ready: AtomicBool,
+    /// Whether card data is being worked on, so searches and card lookups are answered with a 503.
+    /// Starts on with `MAINTENANCE=1` and is switched with `/api/admin/maintenance`.
+    maintenance: AtomicBool,
+    /// When the server started, in seconds since the Unix epoch. Catalog versions start over
+    /// when the server restarts, so this tells them apart.
+    started: u64,
+    /// Results of the latest searches, emptied when the catalog is replaced.
+    search_cache: Mutex<ResultCache>,
+    /// Held for the whole of a reload, so two reloads never make the same catalog version.
+    reloading: Mutex<()>,
+    /// The cards last read from each path of the catalog, when it has several. Shared with the
+    /// blocking tasks that load the catalog.
+    sources: Arc<std::sync::Mutex<SourceCache>>,
+    /// Where the cards are fetched from, when `cards_path` is a URL.
+    remote: Option<Mutex<Remote>>,
+    /// How much matches in each part of a card are worth when ranking search results.
+    weights: Weights,
+    /// The threads pages are rendered on. How many there are is set with `RENDER_THREADS`, and
+    /// defaults to the number of CPUs.
+    renderer: RenderPool,
 }
 
-struct AppState {
-    cards: Arc<RwLock<HashMap<String, Card>>>,
+/// Number of reloads remembered for `/api/catalog/diff`.
+const RELOAD_HISTORY: usize = 100;
+
+/// Seconds clients are told to wait before retrying during maintenance.
+const MAINTENANCE_RETRY_AFTER: u64 = 60;
+
+/// Seconds requests in flight get to finish after a shutdown signal when `SHUTDOWN_GRACE` isn't
+/// set.
+const DEFAULT_SHUTDOWN_GRACE: u64 = 30;
+
+/// The cards a reload changed.
+#[derive(Serialize, Clone)]
+struct ReloadSummary {
+    /// Version of the catalog the reload made.
+    version: u64,
+    /// When the new catalog was swapped in, in seconds since the Unix epoch.
+    timestamp: u64,
+    /// Number of cards before and after the reload.
+    previous_count: usize,
+    count: usize,
+    #[serde(flatten)]
+    diff: CatalogDiff,
+}
+
+/// Whether a reload replaced the catalog.
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum ReloadStatus {
+    Succeeded,
+    Failed,
+}
+
+/// How a reload went, whether it replaced the catalog or not.
+#[derive(Serialize, Clone)]
+struct ReloadAttempt {
+    /// When the reload ended, in seconds since the Unix epoch.
+    timestamp: u64,
+    status: ReloadStatus,
+    /// Number of cards loaded, when the reload succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    count: Option<usize>,
+    /// Why the reload failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// Cards that couldn't be read. A reload can succeed with some of them left out.
+    errors: Vec<CardError>,
+}
+
+impl AppState {
+    /// The state for a catalog that's already loaded, with the rest of the settings read from
+    /// the environment and the page template read from the build, if it's valid.
+    fn from_env(
+        config: Config,
+        strictness: Strictness,
+        catalog: Catalog,
+        report: LoadReport,
+        ready: bool,
+    ) -> Self {
+        Self {
+            catalog: RwLock::new(Arc::new(catalog)),
+            load_report: RwLock::new(report),
+            max_invalid_fraction: env::var("MAX_INVALID_FRACTION")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(0.5),
+            strictness,
+            reloads: RwLock::new(VecDeque::new()),
+            last_attempt: RwLock::new(None),
+            template: RwLock::new(
+                read_template(&config.dist_path)
+                    .ok()
+                    .flatten()
+                    .map(Arc::new),
+            ),
+            ready: AtomicBool::new(ready),
+            maintenance: AtomicBool::new(
+                env::var("MAINTENANCE").is_ok_and(|x| matches!(x.trim(), "1" | "true")),
+            ),
+            started: unix_time(),
+            search_cache: Mutex::new(ResultCache::new(config.search_cache_size)),
+            reloading: Mutex::new(()),
+            sources: Arc::default(),
+            remote: None,
+            weights: Weights::from_env(|x| env::var(x).ok()),
+            renderer: RenderPool::new(
+                env::var("RENDER_THREADS")
+                    .ok()
+                    .and_then(|x| x.parse().ok())
+                    .or_else(|| thread::available_parallelism().ok().map(usize::from))
+                    .unwrap_or(1),
+            )
+            .unwrap_or_else(|x| panic!("Unable to start the page renderers: {x}")),
+            config,
+        }
+    }
+
+    /// The current catalog. Handlers work on this snapshot instead of holding the lock, so a
+    /// long search or serialization never makes a reload wait.
+    async fn catalog(&self) -> Arc<Catalog> {
+        self.catalog.read().await.clone()
+    }
+
+    /// Reads the page template again, keeping the previous one if the new one is invalid.
+    async fn reload_template(&self) {
+        match read_template(&self.config.dist_path) {
+            Ok(template) => {
+                log::info!("Reloaded the page template");
+                *self.template.write().await = template.map(Arc::new);
+            }
+            Err(x) => log::warn!("{x}, keeping the previous template"),
+        }
+    }
+
+    /// Re-reads the catalog, or fetches it if it's remote, and swaps it in, unless too many of
+    /// its cards failed to parse. Returns what the reload changed. Either way, the outcome is
+    /// kept for `/api/admin/last_reload`.
+    async fn reload_catalog(&self) -> Result<ReloadSummary, String> {
+        let text = match &self.remote {
+            Some(remote) => {
+                let fetched = remote.lock().await.fetch().await;
+                match fetched {
+                    Ok(fetched) => Some(fetched.body),
+                    Err(x) => return self.record_attempt(Err(x), vec![]).await,
+                }
+            }
+            None => None,
+        };
+        self.reload_from(text).await
+    }
+
+    /// Swaps in the cards of the JSON `text`, or of the card files if it's nothing, like
+    /// [`AppState::reload_catalog`].
+    async fn reload_from(&self, text: Option<String>) -> Result<ReloadSummary, String> {
+        let mut errors = vec![];
+        let result = self.swap_catalog(&mut errors, text).await;
+        self.record_attempt(result, errors).await
+    }
+
+    /// Keeps how a reload went for `/api/admin/last_reload`, and passes its result on.
+    async fn record_attempt(
+        &self,
+        result: Result<ReloadSummary, String>,
+        errors: Vec<CardError>,
+    ) -> Result<ReloadSummary, String> {
+        *self.last_attempt.write().await = Some(ReloadAttempt {
+            timestamp: unix_time(),
+            status: match result {
+                Ok(_) => ReloadStatus::Succeeded,
+                Err(_) => ReloadStatus::Failed,
+            },
+            count: result.as_ref().ok().map(|x| x.count),
+            message: result.as_ref().err().cloned(),
+            errors,
+        });
+        result
+    }
+
+    /// Does the work of [`AppState::reload_from`], leaving the cards that couldn't be read in
+    /// `errors`.
+    async fn swap_catalog(
+        &self,
+        errors: &mut Vec<CardError>,
+        text: Option<String>,
+    ) -> Result<ReloadSummary, String> {
+        let _reloading = self.reloading.lock().await;
+        // Reading the cards and building the indexes is slow, so it happens away from the
+        // threads that answer requests, which keep using the current catalog meanwhile.
+        let (path, strictness) = (self.config.cards_path.clone(), self.strictness);
+        let (duplicates, sources) = (self.config.duplicate_ids, Arc::clone(&self.sources));
+        let (mut catalog, report) = spawn_blocking(move || match text {
+            Some(text) => parse_cards(&path.to_string_lossy(), &text, strictness, duplicates),
+            None => {
+                let mut sources = sources.lock().unwrap_or_else(PoisonError::into_inner);
+                load_cards(&path, strictness, duplicates, &mut sources)
+            }
+        })
+        .await
+        .map_err(|x| x.to_string())?
+        .map_err(|x| x.to_string())?;
+        report.log();
+        errors.clone_from(&report.errors);
+
+        let rejected = report.invalid_fraction() > self.max_invalid_fraction;
+        let summary = format!(
+            "{} of {} cards failed to parse",
+            report.errors.len(),
+            report.total
+        );
+        *self.load_report.write().await = report;
+        if rejected {
+            return Err(format!("{summary}, keeping the previous catalog"));
+        }
+
+        // Diffing serializes every card, so it's done on a snapshot, and handlers only wait for
+        // the write lock while the new catalog is assigned.
+        let previous = self.catalog().await;
+        catalog.version = previous.version + 1;
+        let summary = ReloadSummary {
+            version: catalog.version,
+            timestamp: unix_time(),
+            previous_count: previous.cards.len(),
+            count: catalog.cards.len(),
+            diff: previous.diff(&catalog),
+        };
+        drop(previous);
+        // Recorded before the new catalog can be read, so diffs always find its version.
+        {
+            let mut reloads = self.reloads.write().await;
+            if reloads.len() == RELOAD_HISTORY {
+                reloads.pop_front();
+            }
+            reloads.push_back(summary.clone());
+        }
+        *self.catalog.write().await = Arc::new(catalog);
+        // Entries of the previous version could never be used again anyway.
+        self.search_cache.lock().await.clear();
+        self.ready.store(true, atomic::Ordering::Relaxed);
+
+        log::info!(
+            "Reloaded {} at {}: {} -> {} cards, {} added, {} removed, {} changed",
+            self.config.cards_path.display(),
+            summary.timestamp,
+            summary.previous_count,
+            summary.count,
+            summary.diff.added.len(),
+            summary.diff.removed.len(),
+            summary.diff.changed.len(),
+        );
+        for (label, ids) in [
+            ("Added", &summary.diff.added),
+            ("Removed", &summary.diff.removed),
+            ("Changed", &summary.diff.changed),
+        ] {
+            if !ids.is_empty() {
+                log::info!("{label}: {}", ids.join(", "));
+            }
+        }
+        Ok(summary)
+    }
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |x| x.as_secs())
+}
+
+/// The UTC date of a moment in seconds since the Unix epoch, written as `YYYY-MM-DD`.
+fn utc_date(seconds: u64) -> String {
+    // Howard Hinnant's civil_from_days, with days counted from 0000-03-01 so leap days come last.
+    let days = seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
 }
 
 #[derive(Serialize)]
@@ -33,177 +368,2785 @@ struct AppState {
 pub enum QueryResult<'a> {
     CardList {
         query_text: String,
-        content: Vec<&'a Card>,
+        /// The matching cards, with only the fields asked for with `fields`.
+        content: Vec<Projected<'a>>,
+        /// Where the query matched each card, by card id. Only sent when asked for with
+        /// `highlight=true`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        highlights: Option<HashMap<String, Highlights>>,
+        /// Cursor for the page after this one, or `null` on the last page. Only sent when the
+        /// results are paginated.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        next_cursor: Option<Option<String>>,
+        /// How many cards matched in all. Only sent when every card was checked, which
+        /// unfiltered pages skip.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        total: Option<usize>,
+        /// Whether there are more pages. Sent instead of `total` when the search stopped at the
+        /// end of the page, which pages without `devours` or `lang` do.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        has_more: Option<bool>,
+        /// Whether more cards matched than the server sends at once, so only the first ones are
+        /// in `content`. `total` still counts all of them. Only sent when it's true.
+        #[serde(skip_serializing_if = "std::ops::Not::not")]
+        truncated: bool,
+        /// Values of the `artist`, `set` and `keyword` filters, by filter. Only sent when any of
+        /// them was given.
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        applied: BTreeMap<&'static str, &'a [String]>,
+        /// How many typos away from the query each card's closest name is, by card id. Only sent
+        /// when typos were allowed, either with `fuzzy=true` or because nothing matched without
+        /// them, so clients can tell the results are approximate.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        distances: Option<HashMap<String, usize>>,
+        /// Whether each card is of the `synergy` kin or mentions it, by card id. Only sent with
+        /// `synergy`.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        synergy: Option<HashMap<String, SynergyMatch>>,
+        /// Names of cards close to the free text of the query. Only sent when nothing matched.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        suggestions: Vec<&'a str>,
     },
     Error {
         message: String,
     },
 }
 
+/// A card as returned by `/api/card`.
+#[derive(Serialize)]
+struct CardView<'a> {
+    /// Only the fields asked for with `fields`.
+    #[serde(flatten)]
+    card: Projected<'a>,
+    /// The card's current id, sent when it was found through an alias or with different casing so
+    /// clients can update their links.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    canonical_id: Option<&'a str>,
+    /// Readable name of the card for links, which `/api/card/by-slug` finds it by.
+    slug: String,
+    /// Other cards sharing functions with this one, with `include=same_function`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    same_function: Option<Vec<SharedFunctions<'a>>>,
+}
+
+#[derive(Serialize)]
+struct SharedFunctions<'a> {
+    /// How many functions the card shares with the requested one.
+    shared: usize,
+    card: Cow<'a, Card>,
+}
+
 #[derive(Deserialize)]
 struct IdViewParam {
     id: String,
+    lang: Option<String>,
+    /// `txt` returns the card as plain text instead of JSON.
+    format: Option<String>,
+    /// `same_function` also returns the cards that share functions with this one.
+    include: Option<String>,
+    /// Only send these fields of the card, separated by commas, like `id,name,cost`.
+    fields: Option<String>,
 }
 
-async fn serve_index(data: web::Data<AppState>, req: HttpRequest) -> io::Result<HttpResponse> {
-    let cards = data.cards.read().await;
-    let path = req.path().to_string();
-    let path = PathBuf::from(path);
-    let card_details = path
-        .iter()
-        .nth(2)
-        .map(|x| x.to_str().unwrap())
-        .and_then(|x| cards.get(x).cloned());
-
-    if path.extension().map_or(false, |x| x == "js") {
-        let content = fs::read_to_string(format!("dist/{}", path.to_string_lossy()))?;
-        Ok(HttpResponse::Ok()
-            .content_type("application/javascript; charset=utf-8")
-            .body(content))
-    } else if path.extension().map_or(false, |x| x == "wasm") {
-        let content = fs::read(format!("dist/{}", path.to_string_lossy()))?;
-        Ok(HttpResponse::Ok()
-            .content_type("application/wasm")
-            .body(content))
-    } else {
-        let content = fs::read_to_string("dist/index.html")?;
-        let path = path.clone();
-        let content = spawn_blocking(move || {
-            use tokio::runtime::Builder;
-            let set = LocalSet::new();
-            let rt = Builder::new_current_thread().enable_all().build().unwrap();
-            set.block_on(&rt, async {
-                let (description, name) = match card_details {
-                    Some(card) => (card.description.clone(), get_filegarden_link(&card.name)),
-                    None => (
-                        "A search engine for Bloodless cards.".to_string(),
-                        String::new(),
-                    ),
-                };
-                let renderer =
-                    ServerRenderer::<hemolymph_frontend::ServerApp>::with_props(move || {
-                        ServerAppProps {
-                            url: path.to_string_lossy().to_string().into(),
-                            queries: HashMap::new(),
-                        }
-                    });
-                content
-                    .replace("{content}", &renderer.render().await)
-                    .replace("{description}", &description)
-                    .replace("{ogimage}", &name)
+/// JSON body sent back when a request can't be served as asked.
+#[derive(Serialize)]
+struct ApiError {
+    message: String,
+}
+
+/// Turns query string extraction failures into a JSON `ApiError` with status 400, instead of
+/// actix's default plain-text response.
+fn query_error_handler(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    let response = HttpResponse::BadRequest().json(ApiError {
+        message: format!("Invalid query parameters: {err}"),
+    });
+    InternalError::from_response(err, response).into()
+}
+
+/// First parts of the paths the frontend has pages for. Other paths are still rendered, but
+/// answered with a 404.
+const FRONTEND_ROUTES: &[&str] = &["", "card", "search", "set", "howto"];
+
+/// Replaces the characters that have a meaning in HTML, so `text` can go inside tags and
+/// attributes as it is.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Most characters of a description in the meta tags, without the ellipsis.
+const MAX_META_DESCRIPTION: usize = 200;
+
+/// `description` on a single line, cut at the last whole word that fits in
+/// [`MAX_META_DESCRIPTION`] characters, with an ellipsis when it's cut.
+fn meta_description(description: &str) -> String {
+    let description = description.split_whitespace().collect::<Vec<_>>().join(" ");
+    if description.chars().count() <= MAX_META_DESCRIPTION {
+        return description;
+    }
+    let Some((end, next)) = description.char_indices().nth(MAX_META_DESCRIPTION) else {
+        return description;
+    };
+    // A word that doesn't fit is left out, unless it's the only one.
+    let cut = match description[..end].rfind(' ') {
+        Some(space) if next != ' ' && space > 0 => &description[..space],
+        _ => &description[..end],
+    };
+    format!(
+        "{}…",
+        cut.trim_end_matches(|x: char| x.is_ascii_punctuation())
+    )
+}
+
+/// A value filled into the page template.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    Content,
+    Description,
+    Image,
+}
+
+/// The placeholders of the page template, which it must have at least once each.
+const PLACEHOLDERS: [(&str, Slot); 3] = [
+    ("{content}", Slot::Content),
+    ("{description}", Slot::Description),
+    ("{ogimage}", Slot::Image),
+];
+
+enum Segment {
+    Text(String),
+    Slot(Slot),
+}
+
+/// The page template the frontend build leaves, split at its placeholders so pages are put
+/// together without going through the whole template again.
+struct PageTemplate {
+    segments: Vec<Segment>,
+}
+
+impl PageTemplate {
+    /// Splits `template` at its placeholders. Fails when any of them is missing, since the page
+    /// would never have that value.
+    fn parse(template: &str) -> Result<Self, String> {
+        let mut segments = vec![];
+        let mut rest = template;
+        while let Some((start, name, slot)) = PLACEHOLDERS
+            .iter()
+            .filter_map(|(name, slot)| Some((rest.find(name)?, *name, *slot)))
+            .min_by_key(|(start, _, _)| *start)
+        {
+            segments.push(Segment::Text(rest[..start].to_string()));
+            segments.push(Segment::Slot(slot));
+            rest = &rest[start + name.len()..];
+        }
+        segments.push(Segment::Text(rest.to_string()));
+
+        let missing: Vec<&str> = PLACEHOLDERS
+            .iter()
+            .filter(|(_, slot)| {
+                !segments
+                    .iter()
+                    .any(|x| matches!(x, Segment::Slot(x) if x == slot))
             })
+            .map(|(name, _)| *name)
+            .collect();
+        if missing.is_empty() {
+            Ok(Self { segments })
+        } else {
+            Err(format!("doesn't have {}", missing.join(", ")))
+        }
+    }
+
+    /// Fills in the template with the rendered page and the values of its meta tags.
+    fn render(&self, content: &str, description: &str, image: &str) -> String {
+        let description = escape_html(&meta_description(description));
+        let image = escape_html(image);
+        let mut page = String::with_capacity(
+            self.segments
+                .iter()
+                .map(|x| match x {
+                    Segment::Text(x) => x.len(),
+                    Segment::Slot(_) => 0,
+                })
+                .sum::<usize>()
+                + content.len(),
+        );
+        for segment in &self.segments {
+            page.push_str(match segment {
+                Segment::Text(x) => x,
+                Segment::Slot(Slot::Content) => content,
+                Segment::Slot(Slot::Description) => &description,
+                Segment::Slot(Slot::Image) => &image,
+            });
+        }
+        page
+    }
+}
+
+/// Reads the page template of the build in `dist`, or nothing when the frontend isn't built.
+fn read_template(dist: &Path) -> Result<Option<PageTemplate>, String> {
+    let path = dist.join(INDEX_FILE);
+    match fs::read_to_string(&path) {
+        Ok(template) => PageTemplate::parse(&template)
+            .map(Some)
+            .map_err(|x| format!("{} {x}", path.display())),
+        Err(x) if x.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(x) => Err(format!("Unable to read {}: {x}", path.display())),
+    }
+}
+
+/// Descriptions of the site for the meta tags of pages that aren't about a card, by language.
+/// The first one is used when none of the client's languages is here.
+const SITE_DESCRIPTIONS: &[(&str, &str)] = &[
+    ("en", "A search engine for Bloodless cards."),
+    ("es", "Un buscador de cartas de Bloodless."),
+    ("pt", "Um buscador de cartas de Bloodless."),
+    ("fr", "Un moteur de recherche de cartes Bloodless."),
+    ("de", "Eine Suchmaschine für Bloodless-Karten."),
+];
+
+/// The description of the site in the first of `languages` it's translated to. Regional tags
+/// like `es-MX` use the translation of their language.
+fn site_description(languages: &[String]) -> &'static str {
+    languages
+        .iter()
+        .find_map(|tag| {
+            let language = tag.split(['-', '_']).next().unwrap_or(tag);
+            SITE_DESCRIPTIONS
+                .iter()
+                .find(|(x, _)| x.eq_ignore_ascii_case(language))
         })
-        .await
-        .unwrap();
-        Ok(HttpResponse::Ok().content_type("text/html").body(content))
+        .map_or(SITE_DESCRIPTIONS[0].1, |(_, description)| description)
+}
+
+/// The page template the frontend build leaves.
+const INDEX_FILE: &str = "index.html";
+
+/// Served instead of every page while the frontend isn't built.
+const FRONTEND_MISSING: &str = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>Hemolymph</title></head>
+<body>
+<h1>The frontend isn't built</h1>
+<p>There's no dist/index.html. Build hemolymph-frontend into dist/ and reload this page. The API
+works without it.</p>
+</body>
+</html>
+";
+
+/// Served instead of a page that failed to render.
+const RENDER_FAILED: &str = "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>Hemolymph</title></head>
+<body>
+<h1>This page couldn't be rendered</h1>
+<p>Something went wrong while rendering it. Reloading may work.</p>
+</body>
+</html>
+";
+
+/// Most characters of the query's description in the description of a search page.
+const MAX_PREVIEW_QUERY: usize = 100;
+
+/// Readies a parsed query to run against `catalog` the way `/api/search` does: kins also match
+/// their descendants, and typo-tolerant restrictions find the cards within reach.
+fn prepare_query(query: &mut Query, catalog: &Catalog) {
+    query.expand_kins(&catalog.kins);
+    query.resolve_typos(&catalog.name_index);
+}
+
+/// The description of a search page, and the image of its card when only one matches. Nothing
+/// when the query is empty or can't be parsed.
+fn search_preview(
+    catalog: &Catalog,
+    query: QueryParams,
+    image_base_url: &str,
+) -> Option<(String, String)> {
+    let mut query = query.parse().ok()?;
+    // Queries that match every card are described as `all cards`, and the rest as `cards where`.
+    let text = query.to_string();
+    let mut condition = text.strip_prefix("cards where ")?.to_string();
+    if condition.chars().count() > MAX_PREVIEW_QUERY {
+        condition = condition.chars().take(MAX_PREVIEW_QUERY).collect();
+        condition.push('…');
+    }
+    prepare_query(&mut query, catalog);
+    let cards = catalog.search(&query, None);
+    let description = match cards.len() {
+        1 => format!("1 card where {condition}"),
+        count => format!("{count} cards where {condition}"),
+    };
+    let image = match cards[..] {
+        [card] => card
+            .image_urls(image_base_url)
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+        _ => String::new(),
+    };
+    Some((description, image))
+}
+
+/// The query string parameters a page is rendered with. When a parameter is repeated, its first
+/// value is used. Empty values are kept, since the frontend reads them as given.
+fn page_queries(pairs: Vec<(String, String)>) -> HashMap<String, String> {
+    let mut queries = HashMap::new();
+    for (key, value) in pairs.into_iter().filter(|(key, _)| !key.is_empty()) {
+        queries.entry(key).or_insert(value);
     }
+    queries
 }
 
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
-    let data = fs::read_to_string("cards.json").expect("Unable to read file");
-    let cards: Vec<Card> = serde_json::from_str(&data).expect("Unable to parse JSON");
-    let cards = create_card_map(cards);
+/// What a page is rendered with: its path, and its query string as [`page_queries`] reads it.
+#[derive(Debug, PartialEq)]
+struct PageProps {
+    url: String,
+    queries: HashMap<String, String>,
+}
 
-    let app_state = web::Data::new(AppState {
-        cards: Arc::new(RwLock::new(cards)),
-    });
+impl PageProps {
+    fn new(req: &HttpRequest) -> Self {
+        Self {
+            url: req.path().to_string(),
+            queries: page_queries(query_pairs(req)),
+        }
+    }
 
-    let environment = env::var("RUST_ENV").unwrap_or_else(|_| "development".to_string());
-    let env_file = match environment.as_str() {
-        "production" => ".env.production",
-        _ => ".env",
+    fn to_props(&self) -> ServerAppProps {
+        ServerAppProps {
+            url: self.url.clone().into(),
+            queries: self.queries.clone(),
+        }
+    }
+}
+
+/// The file of the frontend build in `dist` that `path` names, if there's one. Paths that would
+/// leave the build name no file, and neither does the page template, which is only sent filled
+/// in.
+fn static_file(dist: &Path, path: &str) -> Option<PathBuf> {
+    let path = percent_decode_str(path).decode_utf8().ok()?;
+    let mut file = dist.to_path_buf();
+    for part in path.split('/').filter(|x| !x.is_empty()) {
+        let mut components = Path::new(part).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !part.contains(['\\', '\0']) => file.push(part),
+            _ => return None,
+        }
+    }
+    // Symbolic links could still point outside of the build, so the resolved path is checked too.
+    let dist = fs::canonicalize(dist).ok()?;
+    let file = fs::canonicalize(file).ok()?;
+    (file.starts_with(&dist) && file.is_file() && file != dist.join(INDEX_FILE)).then_some(file)
+}
+
+/// Sends the files of the frontend build as they are, and renders every other path as a page.
+async fn serve_index(data: web::Data<AppState>, req: HttpRequest) -> io::Result<HttpResponse> {
+    // The MIME type comes from the extension, and large files like the wasm can be fetched in
+    // ranges. Finding the file touches the disk, so it happens away from the threads that answer
+    // requests.
+    let (dist, path) = (data.config.dist_path.clone(), req.path().to_string());
+    let file = spawn_blocking(move || static_file(&dist, &path))
+        .await
+        .map_err(io::Error::other)?;
+    if let Some(file) = file {
+        return Ok(NamedFile::open_async(file).await?.into_response(&req));
+    }
+
+    let catalog = data.catalog().await;
+    let route = req.path().trim_start_matches('/').split('/').next();
+    // Only card pages are about a card, whatever the rest of the path of other pages is.
+    let card_details = req
+        .path()
+        .split('/')
+        .nth(2)
+        .filter(|_| route == Some("card"))
+        .and_then(|x| catalog.by_id(x).cloned());
+
+    let Some(template) = data.template.read().await.clone() else {
+        return Ok(HttpResponse::ServiceUnavailable()
+            .content_type("text/html; charset=utf-8")
+            .body(FRONTEND_MISSING));
     };
-    dotenv::from_filename(env_file).ok();
+    // Links to cards that don't exist are as missing as any other unknown page.
+    let found = route.is_some_and(|x| FRONTEND_ROUTES.contains(&x))
+        && (route != Some("card") || card_details.is_some());
+    let image = card_details
+        .as_ref()
+        .and_then(|x| x.image_urls(&data.config.image_base_url).into_iter().next())
+        .unwrap_or_default();
+    let site_description = site_description(&requested_languages(None, &req));
+    let card_route = route == Some("card");
+    // Rendered with the same query as the browser sees, so the page doesn't change on hydration.
+    let props = PageProps::new(&req);
+    let search_query = web::Query::<QueryParams>::from_query(req.query_string())
+        .ok()
+        .filter(|_| route == Some("search"))
+        .map(|x| with_repeated_params(x, &req));
+    let image_base_url = data.config.image_base_url.clone();
+    let ssr = data.config.ssr;
+    let page = data
+        .renderer
+        .render(move || async move {
+            // Searched here, away from the threads that answer requests, like API searches.
+            let preview = search_query.and_then(|x| search_preview(&catalog, x, &image_base_url));
+            let (description, name) = match (card_details, preview) {
+                (Some(card), _) if card.description.trim().is_empty() => {
+                    (site_description.to_string(), image)
+                }
+                (Some(card), _) => (card.description.clone(), image),
+                (None, _) if card_route => ("This card wasn't found.".to_string(), String::new()),
+                (None, _) if !found => ("Not found.".to_string(), String::new()),
+                (None, Some(preview)) => preview,
+                (None, None) => (site_description.to_string(), String::new()),
+            };
+            let content = if ssr {
+                ServerRenderer::<hemolymph_frontend::ServerApp>::with_props(move || {
+                    props.to_props()
+                })
+                .render()
+                .await
+            } else {
+                String::new()
+            };
+            template.render(&content, &description, &name)
+        })
+        .await;
+    let content = match page {
+        Ok(content) => content,
+        Err(x) => {
+            log::error!("Unable to render {}: {x}", req.path());
+            return Ok(HttpResponse::InternalServerError()
+                .content_type("text/html; charset=utf-8")
+                .body(RENDER_FAILED));
+        }
+    };
+    let mut response = if found {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::NotFound()
+    };
+    Ok(response
+        .content_type("text/html; charset=utf-8")
+        .body(content))
+}
+
+/// How long to wait before watching the cards again after the watcher fails.
+const WATCH_RETRY: Duration = Duration::from_secs(5);
 
-    // Read the HOST and PORT variables
-    let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
-    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+/// Reloads the catalog whenever the cards or the kin hierarchy change. If the watcher fails,
+/// the failure is logged and the files are watched again.
+async fn watch_cards(state: web::Data<AppState>) {
+    loop {
+        if let Err(x) = watch_until_error(&state).await {
+            log::error!(
+                "Stopped watching {}: {x}",
+                state.config.cards_path.display()
+            );
+        }
+        sleep(WATCH_RETRY).await;
+    }
+}
 
-    let cards_pointer = Arc::clone(&app_state.cards);
+/// Watches the cards until the watcher reports an error. The task sleeps until an event
+/// arrives, and every event reloads the catalog.
+async fn watch_until_error(state: &AppState) -> Result<(), String> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut debouncer = new_debouncer(Duration::from_secs(1), move |event| {
+        // Only fails once the receiver is gone, and then nobody is waiting for the event.
+        let _ = tx.send(event);
+    })
+    .map_err(|x| x.to_string())?;
 
-    tokio::spawn(async move {
-        let (tx, rx) = std::sync::mpsc::channel();
-        let mut debouncer = new_debouncer(Duration::from_secs(1), tx).unwrap();
+    // Remote cards are fetched on their own schedule, so only the build is watched for them.
+    let cards_path = &state.config.cards_path;
+    let local = state.remote.is_none();
+    let paths = if local {
+        watched_paths(cards_path)
+    } else {
+        vec![]
+    };
+    for path in paths {
+        if path.exists() {
+            debouncer
+                .watcher()
+                .watch(&path, RecursiveMode::Recursive)
+                .map_err(|x| x.to_string())?;
+        } else {
+            // Files can't be watched before they exist, so the folder that will have it is.
+            let parent = path
+                .parent()
+                .filter(|x| !x.as_os_str().is_empty())
+                .unwrap_or(Path::new("."));
+            debouncer
+                .watcher()
+                .watch(parent, RecursiveMode::NonRecursive)
+                .map_err(|x| x.to_string())?;
+        }
+    }
+    // The hierarchy and set order are optional, so they're only watched when they exist at
+    // startup.
+    for path in [loader::kins_path(cards_path), loader::sets_path(cards_path)] {
+        if local && path.exists() {
+            debouncer
+                .watcher()
+                .watch(&path, RecursiveMode::NonRecursive)
+                .map_err(|x| x.to_string())?;
+        }
+    }
+    // The build is watched instead of its index.html, which deploys often replace.
+    let dist = fs::canonicalize(&state.config.dist_path).ok();
+    if let Some(dist) = &dist {
         debouncer
             .watcher()
-            .watch(Path::new("cards.json"), RecursiveMode::Recursive)
-            .unwrap();
-        loop {
-            match rx.try_recv() {
-                Ok(_) => {
-                    let data = fs::read_to_string("cards.json").expect("Unable to read file");
-                    match serde_json::from_str::<Vec<Card>>(&data) {
-                        Ok(data) => {
-                            let mut cards = cards_pointer.write().await;
-                            *cards = create_card_map(data);
-                        }
-                        Err(x) => eprintln!("{x:#?}"),
+            .watch(dist, RecursiveMode::NonRecursive)
+            .map_err(|x| x.to_string())?;
+    }
+
+    while let Some(event) = rx.recv().await {
+        match event {
+            Ok(events) => {
+                let (frontend, cards): (Vec<_>, Vec<_>) = events
+                    .iter()
+                    .partition(|x| dist.as_ref().is_some_and(|dist| x.path.starts_with(dist)));
+                if frontend.iter().any(|x| x.path.ends_with(INDEX_FILE)) {
+                    state.reload_template().await;
+                }
+                if !cards.is_empty() {
+                    if let Err(x) = state.reload_catalog().await {
+                        log::error!("{x}");
                     }
                 }
-                Err(TryRecvError::Empty) => (),
-                Err(x) => eprintln!("{x:#?}"),
             }
-            sleep(Duration::from_secs(0)).await;
+            Err(x) => return Err(format!("{x:?}")),
+        }
+    }
+    Err("the watcher stopped sending events".to_string())
+}
+
+/// Fetches the remote cards every `interval`, reloading the catalog when they changed. A fetch
+/// that fails keeps the current catalog, and is logged and kept for `/api/admin/last_reload` like
+/// a failed reload.
+async fn refresh_cards(state: web::Data<AppState>, interval: Duration) {
+    let Some(remote) = &state.remote else {
+        return;
+    };
+    loop {
+        sleep(interval).await;
+        let fetched = remote.lock().await.fetch().await;
+        let result = match fetched {
+            Ok(fetched) if !fetched.changed => continue,
+            Ok(fetched) => state.reload_from(Some(fetched.body)).await,
+            Err(x) => state.record_attempt(Err(x), vec![]).await,
+        };
+        if let Err(x) = result {
+            log::error!("{x}");
         }
+    }
+}
+
+/// Fetches the cards of `remote` and reads them like a card file.
+async fn fetch_cards(
+    remote: &mut Remote,
+    strictness: Strictness,
+    duplicates: DuplicatePolicy,
+) -> Result<(Catalog, LoadReport), String> {
+    let fetched = remote.fetch().await?;
+    parse_cards(remote.url(), &fetched.body, strictness, duplicates).map_err(|x| x.to_string())
+}
+
+#[actix_web::main]
+async fn main() -> std::io::Result<()> {
+    let environment = env::var("RUST_ENV").unwrap_or_else(|_| "development".to_string());
+    let env_file = match environment.as_str() {
+        "production" => ".env.production",
+        _ => ".env",
+    };
+    dotenv::from_filename(env_file).ok();
+
+    let cli = Cli::parse();
+    let config_path = cli.config.clone().or_else(|| {
+        env::var("CONFIG_PATH")
+            .ok()
+            .filter(|x| !x.trim().is_empty())
+            .map(PathBuf::from)
     });
+    let mut config = Config::load(config_path.as_deref(), |x| env::var(x).ok())
+        .unwrap_or_else(|x| panic!("{x}"));
+    cli.apply(&mut config);
+    log::set_level(config.log_level);
+    let cards_path = config.cards_path.clone();
+    let strictness = Strictness::from_env(env::var("STRICT_CARDS").ok().as_deref());
+    if cli.check {
+        std::process::exit(check_cards(&cards_path, strictness, config.duplicate_ids).await);
+    }
+    log::info!("Configuration:\n{}", config.describe().trim_end());
+
+    // A missing or empty catalog may just not have been written yet, so the server starts empty
+    // and waits for the cards to appear.
+    let mut sources = SourceCache::default();
+    let mut remote = remote::is_url(&cards_path)
+        .then(|| Remote::new(cards_path.to_string_lossy().into_owned()))
+        .transpose()
+        .unwrap_or_else(|x| panic!("{x}"));
+    let loaded = match &mut remote {
+        // Nothing writes remote cards while the server waits, so they have to load right away.
+        Some(remote) => Ok(fetch_cards(remote, strictness, config.duplicate_ids)
+            .await
+            .unwrap_or_else(|x| panic!("{x}"))),
+        None => load_cards(&cards_path, strictness, config.duplicate_ids, &mut sources),
+    };
+    let (catalog, report, ready) = match loaded {
+        Ok((catalog, report)) => (catalog, report, true),
+        Err(x) if x.is_not_found() => {
+            log::warn!(
+                "Warning: {} doesn't exist, starting without cards until it does",
+                cards_path.display()
+            );
+            (Catalog::new(HashMap::new()), LoadReport::default(), false)
+        }
+        Err(x @ CardLoadError::Empty(_)) => {
+            log::warn!("Warning: {x}, starting without cards until it has some");
+            (Catalog::new(HashMap::new()), LoadReport::default(), false)
+        }
+        Err(x) => panic!("{x}"),
+    };
+    report.log();
+    // A template that can't be filled in is a broken deploy, so it stops the server like cards
+    // that can't be loaded.
+    if read_template(&config.dist_path)
+        .unwrap_or_else(|x| panic!("{x}"))
+        .is_none()
+    {
+        log::warn!(
+            "Warning: {} doesn't exist, so every page will only say that the frontend isn't \
+             built. Build hemolymph-frontend into {} first.",
+            config.dist_path.join(INDEX_FILE).display(),
+            config.dist_path.display()
+        );
+    }
 
-    HttpServer::new(move || {
-        let cors = Cors::default().allow_any_origin();
+    let address = (config.host.clone(), config.port);
+    let cors_origins = config.cors_origins.clone();
+    let (workers, keep_alive) = (config.workers, config.keep_alive);
+    let refresh = (remote.is_some() && config.refresh_interval > 0)
+        .then(|| Duration::from_secs(config.refresh_interval));
+    let mut app_state = AppState::from_env(config, strictness, catalog, report, ready);
+    app_state.remote = remote.map(Mutex::new);
+    // Reloads start from what startup read, so a file that breaks later keeps its cards.
+    *app_state
+        .sources
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = sources;
+    let app_state = web::Data::new(app_state);
+
+    let watcher = tokio::spawn(watch_cards(app_state.clone()));
+    if let Some(interval) = refresh {
+        tokio::spawn(refresh_cards(app_state.clone(), interval));
+    }
+    let signal_state = app_state.clone();
+    let grace = env::var("SHUTDOWN_GRACE")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(DEFAULT_SHUTDOWN_GRACE);
+
+    let mut server = HttpServer::new(move || {
+        let cors = if cors_origins.is_empty() {
+            Cors::default().allow_any_origin()
+        } else {
+            cors_origins
+                .iter()
+                .fold(Cors::default(), |cors, x| cors.allowed_origin(x))
+        };
         App::new()
             .wrap(cors)
             .app_data(app_state.clone())
+            .app_data(web::QueryConfig::default().error_handler(query_error_handler))
             .route("/api/search", web::get().to(search))
+            .route("/api/search/explain", web::get().to(explain_search))
+            .route("/api/catalog", web::get().to(full_catalog))
+            .route("/api/catalog.jsonl", web::get().to(catalog_lines))
+            .route("/api/catalog/diff", web::get().to(catalog_diff))
             .route("/api/card", web::get().to(view_card))
+            .route("/api/card/export", web::get().to(export_card))
+            .route("/api/card/by-slug", web::get().to(card_by_slug))
+            .route("/api/card/referenced-by", web::get().to(referenced_by))
+            .route("/api/random", web::get().to(random_card))
+            .route("/api/card-of-the-day", web::get().to(card_of_the_day))
+            .route("/api/reload", web::post().to(admin_reload))
+            .route("/api/ready", web::get().to(ready))
+            .route("/api/health", web::get().to(health))
+            .route("/api/admin/load_report", web::get().to(load_report))
+            .route("/api/admin/last_reload", web::get().to(last_reload))
+            .route("/api/admin/maintenance", web::post().to(set_maintenance))
+            .route("/api/admin/cache_stats", web::get().to(cache_stats))
+            .route("/api/admin/reload", web::post().to(admin_reload))
+            .route("/api/sets", web::get().to(list_sets))
+            .route("/api/rarities", web::get().to(list_rarities))
+            .route("/api/functions", web::get().to(list_functions))
+            .route("/api/keywords", web::get().to(list_keywords))
+            .route("/api/kins", web::get().to(list_kins))
+            .route("/api/tags", web::get().to(list_tags))
+            .route("/api/deck/parse", web::post().to(parse_deck))
+            .route("/api/deck/legality", web::post().to(deck_legality))
             .default_service(web::route().to(serve_index))
     })
-    .bind(format!("{host}:{port}"))?
-    .run()
-    .await
-}
+    .shutdown_timeout(grace)
+    // Signals are handled by handle_signals, so SIGHUP can reload the cards too.
+    .disable_signals();
+    if let Some(workers) = workers {
+        server = server.workers(workers);
+    }
+    if let Some(secs) = keep_alive {
+        server = server.keep_alive(match secs {
+            0 => KeepAlive::Disabled,
+            secs => KeepAlive::Timeout(Duration::from_secs(secs)),
+        });
+    }
+    let server = server.bind(address)?.run();
+    tokio::spawn(handle_signals(server.handle(), signal_state, grace));
+    server.await?;
 
-fn create_card_map(vec: Vec<Card>) -> HashMap<String, Card> {
-    vec.into_iter().map(|x| (x.id.clone(), x)).collect()
+    // Dropping the debouncer along with the task stops its thread before the runtime goes away.
+    watcher.abort();
+    let _ = watcher.await;
+    log::info!("Stopped watching the cards, exiting");
+    Ok(())
 }
 
-async fn search(data: web::Data<AppState>, query: web::Query<QueryParams>) -> impl Responder {
-    let cards = data.cards.read().await;
-    let cards = cards.values();
-
-    match query_parser(&query.query.clone().unwrap_or_default()) {
-        Ok(query_restrictions) => {
-            let results = hemoglobin::search::search(&query_restrictions, cards);
+/// Loads the cards for `--check`, printing what's wrong with them. Returns the exit code, which
+/// is 1 if the catalog or any of its cards couldn't be loaded.
+async fn check_cards(path: &Path, strictness: Strictness, duplicates: DuplicatePolicy) -> i32 {
+    let loaded = if remote::is_url(path) {
+        match Remote::new(path.to_string_lossy().into_owned()) {
+            Ok(mut remote) => fetch_cards(&mut remote, strictness, duplicates).await,
+            Err(x) => Err(x),
+        }
+    } else {
+        load_cards(path, strictness, duplicates, &mut SourceCache::default())
+            .map_err(|x| x.to_string())
+    };
+    match loaded {
+        Ok((_, report)) => {
+            report.log();
+            println!(
+                "{} of {} cards loaded, {} errors, {} warnings",
+                report.loaded,
+                report.total,
+                report.errors.len(),
+                report.warnings.len()
+            );
+            i32::from(!report.errors.is_empty())
+        }
+        Err(x) => {
+            log::error!("Error: {x}");
+            1
+        }
+    }
+}
 
-            let results = QueryResult::CardList {
-                content: results,
-                query_text: format!("{query_restrictions}"),
-            };
+/// Reloads the cards on SIGHUP, and on SIGTERM or SIGINT stops the server, which stops accepting
+/// connections and gives requests in flight `grace` seconds to finish.
+async fn handle_signals(server: ServerHandle, state: web::Data<AppState>, grace: u64) {
+    #[cfg(unix)]
+    let signal = {
+        use tokio::signal::unix::{signal, SignalKind};
 
-            HttpResponse::Ok().json(results)
+        let (Ok(mut terminate), Ok(mut interrupt), Ok(mut hangup)) = (
+            signal(SignalKind::terminate()),
+            signal(SignalKind::interrupt()),
+            signal(SignalKind::hangup()),
+        ) else {
+            log::error!(
+                "Error: Unable to listen for signals, the server won't shut down gracefully"
+            );
+            return;
+        };
+        loop {
+            tokio::select! {
+                _ = hangup.recv() => {
+                    log::info!("Received SIGHUP, reloading {}", state.config.cards_path.display());
+                    // Reloads run on their own, so a signal to stop isn't kept waiting.
+                    let state = state.clone();
+                    tokio::spawn(async move {
+                        if let Err(x) = state.reload_catalog().await {
+                            log::error!("{x}");
+                        }
+                    });
+                }
+                _ = terminate.recv() => break "SIGTERM",
+                _ = interrupt.recv() => break "SIGINT",
+            }
         }
-        Err(error) => {
-            let error = QueryResult::Error {
-                message: format!("Query couldn't be parsed: {error:#?}"),
-            };
-            HttpResponse::Ok().json(error)
+    };
+    #[cfg(not(unix))]
+    let signal = {
+        let _ = &state;
+        if tokio::signal::ctrl_c().await.is_err() {
+            log::error!(
+                "Error: Unable to listen for signals, the server won't shut down gracefully"
+            );
+            return;
         }
+        "Ctrl-C"
+    };
+
+    log::info!("Received {signal}, finishing requests in flight for up to {grace} seconds");
+    let started = Instant::now();
+    server.stop(true).await;
+    log::info!(
+        "Server stopped after {:.1} seconds",
+        started.elapsed().as_secs_f64()
+    );
+}
+
+#[derive(Serialize)]
+struct Readiness {
+    ready: bool,
+}
+
+#[derive(Serialize)]
+struct Health {
+    status: &'static str,
+}
+
+/// Answers as long as the server runs, even before the catalog is loaded and during maintenance,
+/// unlike `/api/ready` and the card data.
+async fn health() -> impl Responder {
+    HttpResponse::Ok().json(Health { status: "ok" })
+}
+
+/// Whether the server has a catalog to serve, answering 503 until it does.
+async fn ready(data: web::Data<AppState>) -> impl Responder {
+    if data.ready.load(atomic::Ordering::Relaxed) {
+        HttpResponse::Ok().json(Readiness { ready: true })
+    } else {
+        HttpResponse::ServiceUnavailable().json(Readiness { ready: false })
     }
 }
 
-async fn view_card(data: web::Data<AppState>, query: web::Query<IdViewParam>) -> impl Responder {
-    let results = data.cards.read().await;
+/// MIME type of MessagePack, which clients can ask for instead of JSON with `Accept`.
+const MSGPACK: &str = "application/msgpack";
 
-    let results: Option<&Card> = results.get(&query.id);
+/// Whether the `Accept` header asks for MessagePack.
+fn wants_msgpack(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| {
+            x.split(',').any(|x| {
+                let mime = x.split(';').next().unwrap_or_default().trim();
+                mime.eq_ignore_ascii_case(MSGPACK)
+                    || mime.eq_ignore_ascii_case("application/x-msgpack")
+            })
+        })
+}
 
-    match results {
-        Some(results) => HttpResponse::Ok().json(results),
-        None => HttpResponse::Ok().body("oops"),
+/// Finishes `response` with `value` as MessagePack if the request asked for it, and as JSON
+/// otherwise.
+fn negotiated(
+    req: &HttpRequest,
+    mut response: HttpResponseBuilder,
+    value: &impl Serialize,
+) -> HttpResponse {
+    // Caches have to keep both versions apart.
+    response.insert_header((header::VARY, "Accept"));
+    if !wants_msgpack(req) {
+        return response.json(value);
+    }
+    match rmp_serde::to_vec_named(value) {
+        Ok(body) => response.content_type(MSGPACK).body(body),
+        Err(x) => HttpResponse::InternalServerError().json(ApiError {
+            message: format!("Couldn't encode the response as MessagePack: {x}"),
+        }),
     }
 }
 
-fn get_filegarden_link(name: &str) -> String {
-    format!(
-        "https://file.garden/ZJSEzoaUL3bz8vYK/bloodlesscards/{}.png",
-        name.replace(' ', "").replace("ä", "a")
-    )
+/// Checks the `ADMIN_TOKEN` secret, given as a bearer token, returning the response to send
+/// instead when it's missing or wrong.
+fn check_token(data: &AppState, req: &HttpRequest) -> Result<(), HttpResponse> {
+    let Some(token) = &data.config.admin_token else {
+        return Err(HttpResponse::NotFound().json(ApiError {
+            message: "Administration endpoints are disabled".to_string(),
+        }));
+    };
+
+    let provided = req
+        .headers()
+        .get("Authorization")
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.strip_prefix("Bearer "));
+
+    // Compared in constant time, so how long it takes doesn't tell how much of a guess was right.
+    let valid = provided.is_some_and(|x| bool::from(x.as_bytes().ct_eq(token.as_bytes())));
+    if valid {
+        Ok(())
+    } else {
+        Err(HttpResponse::Unauthorized().json(ApiError {
+            message: "Missing or invalid admin token".to_string(),
+        }))
+    }
+}
+
+#[derive(Serialize)]
+struct MaintenanceError {
+    message: String,
+    /// Seconds to wait before trying again, also sent as `Retry-After`.
+    retry_after: u64,
+}
+
+/// Returns the response to send instead of card data while the server is in maintenance.
+fn check_maintenance(data: &AppState) -> Result<(), HttpResponse> {
+    if data.maintenance.load(atomic::Ordering::Relaxed) {
+        Err(HttpResponse::ServiceUnavailable()
+            .insert_header((header::RETRY_AFTER, MAINTENANCE_RETRY_AFTER))
+            .json(MaintenanceError {
+                message: "The card database is under maintenance".to_string(),
+                retry_after: MAINTENANCE_RETRY_AFTER,
+            }))
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct Maintenance {
+    enabled: bool,
+}
+
+/// Turns maintenance on or off with a body like `{"enabled": true}`. Requires the
+/// `ADMIN_TOKEN` secret as a bearer token.
+async fn set_maintenance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<Maintenance>,
+) -> impl Responder {
+    if let Err(response) = check_token(&data, &req) {
+        return response;
+    }
+
+    data.maintenance
+        .store(body.enabled, atomic::Ordering::Relaxed);
+    HttpResponse::Ok().json(Maintenance {
+        enabled: body.enabled,
+    })
+}
+
+/// How many cards a reload changed.
+#[derive(Serialize)]
+struct ReloadCounts {
+    version: u64,
+    previous_count: usize,
+    count: usize,
+    added: usize,
+    removed: usize,
+    changed: usize,
+}
+
+/// Re-reads the card file like the watcher does, for filesystems where changes can't be watched.
+/// Requires the `ADMIN_TOKEN` secret as a bearer token. Also served at `/api/reload`, which is
+/// deprecated and kept for the clients that used it before the admin endpoints.
+async fn admin_reload(data: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_token(&data, &req) {
+        return response;
+    }
+
+    match data.reload_catalog().await {
+        Ok(summary) => HttpResponse::Ok().json(ReloadCounts {
+            version: summary.version,
+            previous_count: summary.previous_count,
+            count: summary.count,
+            added: summary.diff.added.len(),
+            removed: summary.diff.removed.len(),
+            changed: summary.diff.changed.len(),
+        }),
+        Err(message) => HttpResponse::UnprocessableEntity().json(ApiError { message }),
+    }
+}
+
+/// The errors and warnings found by the latest attempt to load the catalog.
+async fn load_report(data: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_token(&data, &req) {
+        return response;
+    }
+
+    HttpResponse::Ok().json(&*data.load_report.read().await)
+}
+
+/// How often searches were answered from the search cache.
+async fn cache_stats(data: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_token(&data, &req) {
+        return response;
+    }
+
+    let stats: CacheStats = data.search_cache.lock().await.stats();
+    HttpResponse::Ok().json(stats)
+}
+
+#[derive(Serialize)]
+struct LastReload<'a> {
+    /// What changed in the latest reload that replaced the catalog, if one has.
+    #[serde(flatten)]
+    changes: Option<&'a ReloadSummary>,
+    /// How the latest reload went, even when it failed and kept the previous catalog.
+    attempt: Option<&'a ReloadAttempt>,
+}
+
+/// What changed in the latest reload that replaced the catalog, along with how the latest reload
+/// went, whether it succeeded or failed, and the cards it couldn't read. Both are left out until
+/// there's a reload.
+async fn last_reload(data: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_token(&data, &req) {
+        return response;
+    }
+
+    let reloads = data.reloads.read().await;
+    let attempt = data.last_attempt.read().await;
+    HttpResponse::Ok().json(LastReload {
+        changes: reloads.back(),
+        attempt: attempt.as_ref(),
+    })
+}
+
+async fn search(
+    data: web::Data<AppState>,
+    query: web::Query<QueryParams>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let query = with_repeated_params(query, &req);
+    let catalog = data.catalog().await;
+    let languages = requested_languages(query.lang.as_deref(), &req);
+    let fields = selected_fields(query.fields.as_deref());
+    // Translations are only searched when a language is asked for explicitly.
+    let search_languages = query.lang.is_some().then(|| languages.clone());
+    // Pages are in id order, so unless cards are filtered after the search, the matches can stop
+    // at the first card past the end of the page.
+    let page_size = query.page_size().map(|x| x.min(data.config.max_results));
+    let stop_after = page_size
+        .filter(|_| query.devours.is_none() && search_languages.is_none())
+        .map(|x| x + 1);
+    let run = |restrictions: Query| {
+        run_search(
+            Arc::clone(&catalog),
+            restrictions,
+            search_languages.clone(),
+            query.cursor.clone(),
+            stop_after,
+        )
+    };
+    let cards = |ids: Vec<String>| {
+        ids.iter()
+            .filter_map(|x| catalog.cards.get(x))
+            .collect::<Vec<&Card>>()
+    };
+
+    match query.parse() {
+        Ok(mut query_restrictions) => {
+            prepare_query(&mut query_restrictions, &catalog);
+            let key = query.cache.then(|| {
+                CacheKey::new(
+                    &query_pairs(&req),
+                    search_languages.clone().unwrap_or_default(),
+                )
+            });
+            let cached = match &key {
+                Some(key) => data.search_cache.lock().await.get(key, catalog.version),
+                None => None,
+            };
+            let (query_restrictions, mut results, approximate) = if let Some(cached) = cached {
+                if cached.approximate && !query.fuzzy {
+                    query_restrictions.allow_typos(query.max_distance);
+                    query_restrictions.resolve_typos(&catalog.name_index);
+                }
+                (query_restrictions, cards(cached.ids), cached.approximate)
+            } else {
+                let (mut query_restrictions, ids) = match run(query_restrictions).await {
+                    Ok(found) => found,
+                    Err(x) => return search_failed(&req, &x),
+                };
+                let mut results = cards(ids);
+
+                // Typos are also allowed when the text matches nothing as written.
+                let approximate = query_restrictions.free_text().is_some()
+                    && (query.fuzzy || (results.is_empty() && query.cursor.is_none()));
+                if approximate && !query.fuzzy {
+                    query_restrictions.allow_typos(query.max_distance);
+                    query_restrictions.resolve_typos(&catalog.name_index);
+                    let (restrictions, ids) = match run(query_restrictions).await {
+                        Ok(found) => found,
+                        Err(x) => return search_failed(&req, &x),
+                    };
+                    query_restrictions = restrictions;
+                    results = cards(ids);
+                }
+
+                if let Some(name) = &query.devours {
+                    let Some(target) = catalog.by_name(name) else {
+                        return negotiated(
+                            &req,
+                            HttpResponse::Ok(),
+                            &QueryResult::Error {
+                                message: format!("There's no card named {name}"),
+                            },
+                        );
+                    };
+                    results.retain(|x| x.keyword_targets("devours", target));
+                }
+
+                if query.is_paginated() && query.sort.is_some() {
+                    return negotiated(
+                        &req,
+                        HttpResponse::BadRequest(),
+                        &QueryResult::Error {
+                            message: "Paginated results are ordered by id and can't be sorted"
+                                .to_string(),
+                        },
+                    );
+                }
+
+                match query.sort {
+                    Some(SortOrder::CollectorNumber) => {
+                        results.sort_by(|a, b| a.set.cmp(&b.set).then_with(|| a.collector_cmp(b)));
+                    }
+                    Some(SortOrder::Released) => results.sort_by(|a, b| {
+                        match (a.release_date(), b.release_date()) {
+                            (Some(x), Some(y)) => x.cmp(y),
+                            (Some(_), None) => Ordering::Less,
+                            (None, Some(_)) => Ordering::Greater,
+                            (None, None) => Ordering::Equal,
+                        }
+                        .then_with(|| a.name.cmp(&b.name))
+                    }),
+                    Some(SortOrder::Relevance) => results.sort_by_cached_key(|x| {
+                        (Reverse(query_restrictions.relevance(x)), x.id.clone())
+                    }),
+                    None if query.is_paginated() => results.sort_by(|a, b| a.id.cmp(&b.id)),
+                    None if approximate => {
+                        results.sort_by_cached_key(|x| {
+                            (query_restrictions.typo_score(x), x.name.clone())
+                        });
+                    }
+                    None if query_restrictions.free_text().is_some() => {
+                        let mut scored: Vec<(f32, &Card)> = results
+                            .into_iter()
+                            .map(|x| (query_restrictions.score(x, &data.weights), x))
+                            .collect();
+                        scored.sort_by(|a, b| {
+                            b.0.total_cmp(&a.0).then_with(|| a.1.name.cmp(&b.1.name))
+                        });
+                        results = scored.into_iter().map(|(_, x)| x).collect();
+                    }
+                    None => (),
+                }
+
+                if let Some(key) = key {
+                    let ids = results.iter().map(|x| x.id.clone()).collect();
+                    data.search_cache.lock().await.insert(
+                        key,
+                        catalog.version,
+                        CachedSearch { ids, approximate },
+                    );
+                }
+                (query_restrictions, results, approximate)
+            };
+
+            // Only when nothing matched at all, not when a page past the last one was asked for.
+            let suggestions = match query_restrictions.free_text() {
+                Some(text) if results.is_empty() && query.cursor.is_none() => {
+                    search::suggestions(&text, catalog.cards.values())
+                }
+                _ => vec![],
+            };
+
+            let (total, has_more) = match (stop_after, page_size) {
+                (Some(_), Some(limit)) => (None, Some(results.len() > limit)),
+                _ => (Some(results.len()), None),
+            };
+            let next_cursor = page_size.map(|limit| {
+                let next = (results.len() > limit).then(|| results[limit - 1].id.clone());
+                results.truncate(limit);
+                next
+            });
+            // Pages are already capped, and the next one has the rest.
+            let truncated = results.len() > data.config.max_results;
+            results.truncate(data.config.max_results);
+            let synergy = query.synergy.as_deref().map(|kin| {
+                results
+                    .iter()
+                    .filter_map(|x| Some((x.id.clone(), search::synergy(x, kin.trim())?)))
+                    .collect()
+            });
+            let distances = approximate.then(|| {
+                results
+                    .iter()
+                    .map(|x| (x.id.clone(), query_restrictions.typo_score(x)))
+                    .collect()
+            });
+
+            let content: Vec<Cow<Card>> = results
+                .into_iter()
+                .map(|x| x.localized(&languages))
+                .collect();
+            // Matched against the text that's sent, so the ranges point into the translation.
+            let highlights = query.highlight.then(|| {
+                content
+                    .iter()
+                    .map(|x| (x.id.clone(), query_restrictions.highlights(x)))
+                    .collect()
+            });
+            let content = content
+                .into_iter()
+                .map(|card| Projected {
+                    card,
+                    fields: fields.as_ref(),
+                })
+                .collect();
+            let results = QueryResult::CardList {
+                content,
+                highlights,
+                next_cursor,
+                total,
+                has_more,
+                truncated,
+                applied: query.applied_filters(),
+                distances,
+                synergy,
+                suggestions,
+                query_text: format!("{query_restrictions}"),
+            };
+
+            negotiated(&req, HttpResponse::Ok(), &results)
+        }
+        Err(Errors::InvalidRegex(message)) => negotiated(
+            &req,
+            HttpResponse::BadRequest(),
+            &QueryResult::Error {
+                message: format!("Invalid regex: {message}"),
+            },
+        ),
+        Err(error) => {
+            let error = QueryResult::Error {
+                message: format!("Query couldn't be parsed: {error}"),
+            };
+            negotiated(&req, HttpResponse::Ok(), &error)
+        }
+    }
+}
+
+/// The search parameters together with the ones that can be repeated, like `exclude_type`, all
+/// normalized with [`QueryParams::normalize`].
+fn with_repeated_params(query: web::Query<QueryParams>, req: &HttpRequest) -> QueryParams {
+    let mut query = query.into_inner();
+    query.read_repeated(&query_pairs(req));
+    query.normalize();
+    query
+}
+
+/// Every `key=value` pair of the query string, in order.
+fn query_pairs(req: &HttpRequest) -> Vec<(String, String)> {
+    web::Query::<Vec<(String, String)>>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default()
+}
+
+/// How `/api/search/explain` understood a query.
+#[derive(Serialize)]
+struct Explanation {
+    /// The same description `/api/search` sends as `query_text`.
+    query_text: String,
+    query: Query,
+}
+
+/// Finds the ids of the cards that match `query`, or whose translation to one of `languages`
+/// does, and whose id comes after `after`. With `stop_after`, only that many are looked for,
+/// in id order. Runs away from the threads that answer requests, since checking many cards
+/// takes a while, and gives the query back with the ids. Fails if the search panics.
+async fn run_search(
+    catalog: Arc<Catalog>,
+    query: Query,
+    languages: Option<Vec<String>>,
+    after: Option<String>,
+    stop_after: Option<usize>,
+) -> Result<(Query, Vec<String>), String> {
+    spawn_blocking(move || {
+        let after = after.as_deref();
+        let cards = match &languages {
+            Some(languages) => {
+                let cards = catalog
+                    .cards
+                    .values()
+                    .filter(|x| after.is_none_or(|after| x.id.as_str() > after));
+                search::search_localized(&query, cards, languages)
+            }
+            None => match stop_after {
+                Some(count) => catalog.first_matches(&query, after, count),
+                None => catalog.search(&query, after),
+            },
+        };
+        let ids = cards.into_iter().map(|x| x.id.clone()).collect();
+        (query, ids)
+    })
+    .await
+    .map_err(|x| x.to_string())
+}
+
+/// The response to a search that failed while it ran, with the reason logged.
+fn search_failed(req: &HttpRequest, error: &str) -> HttpResponse {
+    log::error!("Unable to search {}: {error}", req.query_string());
+    negotiated(
+        req,
+        HttpResponse::InternalServerError(),
+        &QueryResult::Error {
+            message: "The search failed".to_string(),
+        },
+    )
+}
+
+/// Parses a search without running it, returning the restrictions it turned into. Parameters
+/// applied after the search, like `devours`, `sort` and pagination, aren't included.
+async fn explain_search(
+    data: web::Data<AppState>,
+    query: web::Query<QueryParams>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    match with_repeated_params(query, &req).parse() {
+        Ok(mut query) => {
+            query.expand_kins(&data.catalog().await.kins);
+            HttpResponse::Ok().json(Explanation {
+                query_text: query.to_string(),
+                query,
+            })
+        }
+        Err(error) => HttpResponse::BadRequest().json(ApiError {
+            message: format!("Query couldn't be parsed: {error}"),
+        }),
+    }
+}
+
+#[derive(Serialize)]
+struct CatalogDump<'a> {
+    version: u64,
+    /// Every card, sorted by id.
+    cards: Vec<&'a Card>,
+}
+
+/// The `ETag` of a catalog, made from its version.
+fn catalog_etag(data: &AppState, catalog: &Catalog) -> String {
+    format!("\"{}-{}\"", data.started, catalog.version)
+}
+
+/// Whether the request's `If-None-Match` has `etag`, so the client already has the response.
+fn not_modified(req: &HttpRequest, etag: &str) -> bool {
+    req.headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|x| x.to_str().ok())
+        .is_some_and(|x| x.split(',').any(|x| x.trim() == etag || x.trim() == "*"))
+}
+
+/// Every card at once, for clients that keep their own copy. The `ETag` is made from the catalog
+/// version, so clients can send it back in `If-None-Match` and get a 304 while nothing changed.
+async fn full_catalog(data: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let etag = catalog_etag(&data, &catalog);
+    if not_modified(&req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish();
+    }
+
+    let mut cards: Vec<&Card> = catalog.cards.values().collect();
+    cards.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .json(CatalogDump {
+            version: catalog.version,
+            cards,
+        })
+}
+
+/// Every card as JSON Lines, one card per line sorted by id, so clients can read them as they
+/// arrive. Cards are serialized one at a time from a snapshot of the catalog, so reloads don't
+/// wait for the transfer. Uses the same `ETag` as `/api/catalog`.
+async fn catalog_lines(data: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let etag = catalog_etag(&data, &catalog);
+    if not_modified(&req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header((header::ETAG, etag))
+            .finish();
+    }
+
+    let lines = (0..catalog.sorted_ids.len()).map(move |i| {
+        let mut line = serde_json::to_vec(&catalog.cards[&catalog.sorted_ids[i]])?;
+        line.push(b'\n');
+        Ok::<_, serde_json::Error>(web::Bytes::from(line))
+    });
+    HttpResponse::Ok()
+        .insert_header((header::ETAG, etag))
+        .content_type("application/x-ndjson")
+        .streaming(futures_util::stream::iter(lines))
+}
+
+#[derive(Deserialize)]
+struct DiffParams {
+    since: u64,
+}
+
+#[derive(Serialize)]
+struct CatalogChanges<'a> {
+    version: u64,
+    /// The server can't tell what changed since the version given, so the client has to fetch
+    /// the whole catalog again. `cards` and `removed` are empty when it's set.
+    resync: bool,
+    /// Cards added or changed since the version given, sorted by id.
+    cards: Vec<&'a Card>,
+    /// Ids of the cards removed since the version given, sorted.
+    removed: Vec<&'a str>,
+}
+
+/// What changed in the catalog since a version given by `/api/catalog`. Only the latest
+/// reloads are remembered, so older versions, and versions from before a restart, need a resync.
+async fn catalog_diff(data: web::Data<AppState>, query: web::Query<DiffParams>) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let reloads = data.reloads.read().await;
+    let mut changes = CatalogChanges {
+        version: catalog.version,
+        resync: false,
+        cards: vec![],
+        removed: vec![],
+    };
+
+    let newer: Vec<&ReloadSummary> = reloads
+        .iter()
+        .filter(|x| x.version > query.since && x.version <= catalog.version)
+        .collect();
+    // Every version after `since` must be remembered for the diff to be complete.
+    let complete = (1..=catalog.version).contains(&query.since)
+        && u64::try_from(newer.len()).is_ok_and(|x| x == catalog.version - query.since);
+    if !complete {
+        changes.resync = true;
+        return HttpResponse::Ok().json(changes);
+    }
+
+    let mut ids: Vec<&str> = newer
+        .iter()
+        .flat_map(|x| {
+            x.diff
+                .added
+                .iter()
+                .chain(&x.diff.removed)
+                .chain(&x.diff.changed)
+        })
+        .map(String::as_str)
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    // A card counts by how it is now, whatever happened to it in between.
+    for id in ids {
+        match catalog.cards.get(id) {
+            Some(card) => changes.cards.push(card),
+            None => changes.removed.push(id),
+        }
+    }
+
+    HttpResponse::Ok().json(changes)
+}
+
+async fn view_card(
+    data: web::Data<AppState>,
+    query: web::Query<IdViewParam>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+
+    let results: Option<&Card> = catalog.by_id(&query.id);
+    let languages = requested_languages(query.lang.as_deref(), &req);
+    let fields = selected_fields(query.fields.as_deref());
+
+    match (results, query.format.as_deref()) {
+        (None, _) => negotiated(
+            &req,
+            HttpResponse::NotFound(),
+            &ApiError {
+                message: format!("There's no card with id {}", query.id),
+            },
+        ),
+        (Some(results), None | Some("json")) => negotiated(
+            &req,
+            HttpResponse::Ok(),
+            &CardView {
+                canonical_id: (results.id != query.id).then_some(results.id.as_str()),
+                slug: catalog.slug(results),
+                card: Projected {
+                    card: results.localized(&languages),
+                    fields: fields.as_ref(),
+                },
+                same_function: (query.include.as_deref() == Some("same_function")).then(|| {
+                    catalog
+                        .sharing_functions(results)
+                        .into_iter()
+                        .map(|(card, shared)| SharedFunctions {
+                            shared,
+                            card: card.localized(&languages),
+                        })
+                        .collect()
+                }),
+            },
+        ),
+        (Some(results), Some("txt")) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(results.localized(&languages).to_string()),
+        (Some(_), Some(format)) => negotiated(
+            &req,
+            HttpResponse::BadRequest(),
+            &ApiError {
+                message: format!("Unknown format {format}, expected json or txt"),
+            },
+        ),
+    }
+}
+
+#[derive(Serialize)]
+struct ReferencingCard<'a> {
+    /// Keywords of the card whose card description the requested card fits, like Devours.
+    keywords: Vec<&'a str>,
+    /// Whether the card's description or abilities mention the requested card by name.
+    mentions: bool,
+    card: Cow<'a, Card>,
+}
+
+#[derive(Deserialize)]
+struct CardParams {
+    id: String,
+    lang: Option<String>,
+}
+
+/// The cards that refer to a card, through keywords like Devours or by name in their text,
+/// sorted by id.
+async fn referenced_by(
+    data: web::Data<AppState>,
+    query: web::Query<CardParams>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let Some(card) = catalog.by_id(&query.id) else {
+        return negotiated(
+            &req,
+            HttpResponse::NotFound(),
+            &ApiError {
+                message: format!("No card has the id {:?}", query.id),
+            },
+        );
+    };
+
+    let languages = requested_languages(query.lang.as_deref(), &req);
+    let cards: Vec<ReferencingCard> = catalog
+        .referencing(card)
+        .into_iter()
+        .map(|x| ReferencingCard {
+            keywords: x.keywords,
+            mentions: x.mentions,
+            card: x.card.localized(&languages),
+        })
+        .collect();
+    negotiated(&req, HttpResponse::Ok(), &cards)
+}
+
+#[derive(Deserialize)]
+struct SlugParams {
+    slug: String,
+    lang: Option<String>,
+}
+
+/// Finds a card by the slug `/api/card` sends with it, like `vampire-mantis`.
+async fn card_by_slug(
+    data: web::Data<AppState>,
+    query: web::Query<SlugParams>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let Some(card) = catalog.by_slug(&query.slug) else {
+        return negotiated(
+            &req,
+            HttpResponse::NotFound(),
+            &ApiError {
+                message: format!("No card has the slug {:?}", query.slug),
+            },
+        );
+    };
+
+    let languages = requested_languages(query.lang.as_deref(), &req);
+    negotiated(
+        &req,
+        HttpResponse::Ok(),
+        &CardView {
+            canonical_id: None,
+            slug: catalog.slug(card),
+            card: Projected {
+                card: card.localized(&languages),
+                fields: None,
+            },
+            same_function: None,
+        },
+    )
+}
+
+#[derive(Deserialize)]
+struct RandomParams {
+    /// Picks the same card every time for the same catalog. Include the catalog version or a date
+    /// in it, like `2024-06-01`, to get a new card when that changes.
+    seed: Option<String>,
+    lang: Option<String>,
+}
+
+/// Picks a position below `len` from `seed`, the same one every time, or a random one without
+/// a seed. `len` can't be 0.
+#[allow(clippy::cast_possible_truncation)]
+fn random_index(seed: Option<&str>, len: usize) -> usize {
+    // FNV-1a, which unlike the standard hashers is the same everywhere and forever.
+    let value = match seed {
+        Some(seed) => seed.bytes().fold(0xcbf2_9ce4_8422_2325, |hash: u64, x| {
+            (hash ^ u64::from(x)).wrapping_mul(0x0100_0000_01b3)
+        }),
+        None => RandomState::new().build_hasher().finish(),
+    };
+    // The SplitMix64 finalizer, so seeds that differ in one character pick far apart cards.
+    let mut x = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    (x % len as u64) as usize
+}
+
+/// A random card, or with `seed`, the one that seed picks from the cards sorted by id. The same
+/// seed picks the same card until the catalog adds or removes cards, which can change which one
+/// it picks, unless the seed changes with the catalog version too.
+async fn random_card(
+    data: web::Data<AppState>,
+    query: web::Query<RandomParams>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    if catalog.sorted_ids.is_empty() {
+        return HttpResponse::NotFound().json(ApiError {
+            message: "There are no cards".to_string(),
+        });
+    }
+
+    let id = &catalog.sorted_ids[random_index(query.seed.as_deref(), catalog.sorted_ids.len())];
+    let languages = requested_languages(query.lang.as_deref(), &req);
+    HttpResponse::Ok().json(catalog.cards[id].localized(&languages))
+}
+
+#[derive(Deserialize)]
+struct CardOfTheDayParams {
+    /// Shows the card of another day, written as `YYYY-MM-DD`, instead of today's.
+    date: Option<String>,
+    lang: Option<String>,
+}
+
+#[derive(Serialize)]
+struct CardOfTheDay<'a> {
+    /// The UTC day the card is for.
+    date: String,
+    card: Cow<'a, Card>,
+}
+
+/// The card featured today, which changes at midnight UTC. It's the card `/api/random` picks with
+/// the date as its seed, so it only changes during the day if the catalog adds or removes cards.
+async fn card_of_the_day(
+    data: web::Data<AppState>,
+    query: web::Query<CardOfTheDayParams>,
+    req: HttpRequest,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let date = match &query.date {
+        Some(date) if !is_iso_date(date.trim()) => {
+            return HttpResponse::BadRequest().json(ApiError {
+                message: format!("{date} isn't a YYYY-MM-DD date"),
+            });
+        }
+        Some(date) => date.trim().to_string(),
+        None => utc_date(unix_time()),
+    };
+    let catalog = data.catalog().await;
+    if catalog.sorted_ids.is_empty() {
+        return HttpResponse::NotFound().json(ApiError {
+            message: "There are no cards".to_string(),
+        });
+    }
+
+    let id = &catalog.sorted_ids[random_index(Some(&date), catalog.sorted_ids.len())];
+    let languages = requested_languages(query.lang.as_deref(), &req);
+    HttpResponse::Ok().json(CardOfTheDay {
+        date,
+        card: catalog.cards[id].localized(&languages),
+    })
+}
+
+#[derive(Deserialize)]
+struct IdParam {
+    id: String,
+}
+
+/// A card as it is in the data, together with values computed from it.
+#[derive(Serialize)]
+struct CardExport<'a> {
+    card: &'a Card,
+    derived: DerivedData<'a>,
+}
+
+#[derive(Serialize)]
+struct DerivedData<'a> {
+    /// Power, health and defense added up, leaving out the ones that aren't fixed numbers.
+    stat_total: usize,
+    image_url: String,
+    /// Formats the card can be played in, sorted.
+    playable_in: Vec<&'a str>,
+    /// Formats the card lists but can't be played in, sorted.
+    not_playable_in: Vec<&'a str>,
+}
+
+/// Exports a single card for sharing, with derived data kept apart from the card itself.
+async fn export_card(data: web::Data<AppState>, query: web::Query<IdParam>) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let Some(card) = catalog.by_id(&query.id) else {
+        return HttpResponse::NotFound().json(ApiError {
+            message: format!("There's no card with id {}", query.id),
+        });
+    };
+
+    let mut playable_in = vec![];
+    let mut not_playable_in = vec![];
+    for (format, legality) in &card.legality {
+        if legality.status.is_playable() {
+            playable_in.push(format.as_str());
+        } else {
+            not_playable_in.push(format.as_str());
+        }
+    }
+    playable_in.sort_unstable();
+    not_playable_in.sort_unstable();
+
+    HttpResponse::Ok().json(CardExport {
+        card,
+        derived: DerivedData {
+            stat_total: [card.power, card.health, card.defense]
+                .into_iter()
+                .filter_map(Stat::fixed)
+                .sum(),
+            image_url: card
+                .image_urls(&data.config.image_base_url)
+                .into_iter()
+                .next()
+                .unwrap_or_default(),
+            playable_in,
+            not_playable_in,
+        },
+    })
+}
+
+/// The languages to show card text in, most preferred first. An explicit `lang` parameter wins
+/// over the `Accept-Language` header, whose languages with a quality of 0 aren't acceptable and are
+/// left out.
+fn requested_languages(lang: Option<&str>, req: &HttpRequest) -> Vec<String> {
+    if let Some(lang) = lang {
+        return vec![lang.to_string()];
+    }
+
+    let Some(accepted) = req
+        .headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|x| x.to_str().ok())
+    else {
+        return vec![];
+    };
+
+    let mut languages: Vec<(String, f32)> = accepted
+        .split(',')
+        .filter_map(|entry| {
+            let mut parts = entry.split(';');
+            let tag = parts.next()?.trim();
+            if tag.is_empty() || tag == "*" {
+                return None;
+            }
+            let quality = parts
+                .find_map(|x| x.trim().strip_prefix("q="))
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then(|| (tag.to_string(), quality))
+        })
+        .collect();
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// Resolves a plain text decklist with one `3 Card Name` line per card.
+async fn parse_deck(data: web::Data<AppState>, body: String) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    HttpResponse::Ok().json(deck::resolve(&body, &catalog))
+}
+
+#[derive(Deserialize)]
+struct FormatParam {
+    format: String,
+}
+
+/// Checks whether a plain text decklist can be played in `format`.
+async fn deck_legality(
+    data: web::Data<AppState>,
+    query: web::Query<FormatParam>,
+    body: String,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let deck = deck::resolve(&body, &catalog);
+    HttpResponse::Ok().json(deck::check_legality(deck, &query.format))
+}
+
+#[derive(Serialize)]
+struct SetSummary {
+    name: String,
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    order: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct SetParams {
+    name: Option<String>,
+}
+
+/// Lists every set with how many cards it has. Sets listed in the optional `sets.json` next to
+/// the cards (a map from set name to its release order) come first in that order, and the rest
+/// follow alphabetically.
+///
+/// With a `name`, lists the cards of that set in collector number order instead.
+async fn list_sets(data: web::Data<AppState>, query: web::Query<SetParams>) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    if let Some(name) = &query.name {
+        let catalog = data.catalog().await;
+        let mut cards: Vec<&Card> = catalog.cards.values().filter(|x| &x.set == name).collect();
+        cards.sort_by(|a, b| a.collector_cmp(b));
+        return HttpResponse::Ok().json(cards);
+    }
+
+    let catalog = data.catalog().await;
+    let order = &catalog.set_order;
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for card in catalog.cards.values() {
+        *counts.entry(card.set.clone()).or_default() += 1;
+    }
+
+    let mut sets: Vec<SetSummary> = counts
+        .into_iter()
+        .map(|(name, count)| SetSummary {
+            order: order.get(&name).copied(),
+            name,
+            count,
+        })
+        .collect();
+    sets.sort_by(|a, b| match (a.order, b.order) {
+        (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.name.cmp(&b.name)),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.name.cmp(&b.name),
+    });
+
+    HttpResponse::Ok().json(sets)
+}
+
+#[derive(Serialize)]
+struct RaritySummary {
+    name: String,
+    count: usize,
+}
+
+/// Lists every rarity cards have, alphabetically, with how many cards have it.
+async fn list_rarities(data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for rarity in data
+        .catalog
+        .read()
+        .await
+        .cards
+        .values()
+        .filter_map(Card::get_rarity)
+    {
+        *counts.entry(rarity.to_string()).or_default() += 1;
+    }
+
+    let mut rarities: Vec<RaritySummary> = counts
+        .into_iter()
+        .map(|(name, count)| RaritySummary { name, count })
+        .collect();
+    rarities.sort_by(|a, b| a.name.cmp(&b.name));
+
+    HttpResponse::Ok().json(rarities)
+}
+
+#[derive(Serialize)]
+struct TagSummary {
+    name: String,
+    /// Number of cards with the tag.
+    count: usize,
+}
+
+/// Lists every tag in the `other` field of cards, with how many cards have it, most common
+/// first. They can be searched with `other`. Tags are counted the way `other` matches them, so
+/// `Café` and `cafe` are one tag, listed with the first of its spellings in alphabetical order.
+async fn list_tags(data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let mut counts: HashMap<String, (&str, usize)> = HashMap::new();
+    for card in catalog.cards.values() {
+        let tags: HashMap<String, &str> =
+            card.other.iter().map(|x| (fold(x), x.as_str())).collect();
+        for (key, tag) in tags {
+            let (name, count) = counts.entry(key).or_insert((tag, 0));
+            *name = (*name).min(tag);
+            *count += 1;
+        }
+    }
+
+    let mut tags: Vec<TagSummary> = counts
+        .into_values()
+        .map(|(name, count)| TagSummary {
+            name: name.to_string(),
+            count,
+        })
+        .collect();
+    tags.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+
+    HttpResponse::Ok().json(tags)
+}
+
+#[derive(Serialize)]
+struct KeywordSummary {
+    name: String,
+    /// Number of cards with the keyword on any face.
+    count: usize,
+    /// Every text the keyword carries, like the kin of a keyword that names one.
+    #[serde(skip_serializing_if = "BTreeSet::is_empty")]
+    values: BTreeSet<String>,
+    /// Lowest number the keyword carries, for keywords like Armor 2.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    min: Option<i64>,
+    /// Highest number the keyword carries.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max: Option<i64>,
+}
+
+/// Lists every keyword cards have, with how many cards have it, most used first and then
+/// alphabetically.
+async fn list_keywords(data: web::Data<AppState>) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+    let mut keywords: HashMap<&str, KeywordSummary> = HashMap::new();
+    for card in catalog.cards.values() {
+        let mut seen = HashSet::new();
+        let faces = card.faces.iter().flat_map(|x| &x.keywords);
+        for keyword in card.keywords.iter().chain(faces) {
+            let summary = keywords
+                .entry(&keyword.name)
+                .or_insert_with(|| KeywordSummary {
+                    name: keyword.name.clone(),
+                    count: 0,
+                    values: BTreeSet::new(),
+                    min: None,
+                    max: None,
+                });
+            if seen.insert(keyword.name.as_str()) {
+                summary.count += 1;
+            }
+            if let Some(KeywordData::String(value)) = &keyword.data {
+                summary.values.insert(value.clone());
+            }
+            if let Some(value) = keyword.value() {
+                summary.min = Some(summary.min.map_or(value, |x| x.min(value)));
+                summary.max = Some(summary.max.map_or(value, |x| x.max(value)));
+            }
+        }
+    }
+
+    let mut keywords: Vec<KeywordSummary> = keywords.into_values().collect();
+    keywords.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.name.cmp(&b.name)));
+
+    HttpResponse::Ok().json(keywords)
+}
+
+#[derive(Serialize)]
+struct FunctionSummary {
+    name: String,
+    count: usize,
+}
+
+#[derive(Deserialize)]
+struct FunctionParams {
+    name: Option<String>,
+}
+
+/// Lists every function cards declare, alphabetically, with how many cards declare it. Functions
+/// are compared trimmed and ignoring case.
+///
+/// With a `name`, lists the cards that declare that function instead, sorted by id.
+async fn list_functions(
+    data: web::Data<AppState>,
+    query: web::Query<FunctionParams>,
+) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    let catalog = data.catalog().await;
+
+    if let Some(name) = &query.name {
+        let name = name.trim().to_lowercase();
+        let mut cards: Vec<&Card> = catalog
+            .cards
+            .values()
+            .filter(|x| x.normalized_functions().contains(&name))
+            .collect();
+        cards.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        return HttpResponse::Ok().json(cards);
+    }
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for function in catalog.cards.values().flat_map(Card::normalized_functions) {
+        *counts.entry(function).or_default() += 1;
+    }
+
+    let mut functions: Vec<FunctionSummary> = counts
+        .into_iter()
+        .map(|(name, count)| FunctionSummary { name, count })
+        .collect();
+    functions.sort_by(|a, b| a.name.cmp(&b.name));
+
+    HttpResponse::Ok().json(functions)
+}
+
+#[derive(Deserialize)]
+struct KinParams {
+    #[serde(default)]
+    tree: bool,
+}
+
+#[derive(Serialize)]
+struct KinSummary {
+    name: String,
+    /// Cards with this kin or a kin within it.
+    count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parent: Option<String>,
+    /// Kins that belong to this one, only sent with `tree=true`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    children: Vec<KinSummary>,
+}
+
+/// Lists every kin, alphabetically, with how many cards have it or a kin within it. Kins come
+/// from the cards and from `kin_hierarchy.json`.
+///
+/// With `tree=true`, only kins that don't belong to another are listed, each with its children.
+async fn list_kins(data: web::Data<AppState>, query: web::Query<KinParams>) -> impl Responder {
+    if let Err(response) = check_maintenance(&data) {
+        return response;
+    }
+    fn summary(name: &str, catalog: &Catalog, card_kins: &[Vec<String>], tree: bool) -> KinSummary {
+        let children = if tree {
+            let mut children: Vec<&str> = all_kins(catalog, card_kins)
+                .into_iter()
+                .filter(|x| catalog.kins.parent(x) == Some(name))
+                .collect();
+            children.sort_unstable();
+            children
+                .into_iter()
+                .map(|x| summary(x, catalog, card_kins, tree))
+                .collect()
+        } else {
+            vec![]
+        };
+        KinSummary {
+            name: name.to_string(),
+            count: card_kins
+                .iter()
+                .filter(|kins| kins.iter().any(|x| catalog.kins.is_within(x, name)))
+                .count(),
+            parent: catalog.kins.parent(name).map(str::to_string),
+            children,
+        }
+    }
+    fn all_kins<'a>(catalog: &'a Catalog, card_kins: &'a [Vec<String>]) -> Vec<&'a str> {
+        let mut kins: Vec<&str> = card_kins
+            .iter()
+            .flatten()
+            .map(String::as_str)
+            .chain(catalog.kins.kins())
+            .collect();
+        kins.sort_unstable();
+        kins.dedup();
+        kins
+    }
+
+    let catalog = data.catalog().await;
+    let card_kins: Vec<Vec<String>> = catalog
+        .cards
+        .values()
+        .map(|card| card.kins.iter().map(|x| x.trim().to_lowercase()).collect())
+        .collect();
+
+    let kins: Vec<KinSummary> = all_kins(&catalog, &card_kins)
+        .into_iter()
+        .filter(|x| !query.tree || catalog.kins.parent(x).is_none())
+        .map(|x| summary(x, &catalog, &card_kins, query.tree))
+        .collect();
+
+    HttpResponse::Ok().json(kins)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::kins::KinHierarchy;
+    use crate::test_cards::{card_json, card_with};
+
+    #[test]
+    fn seeds_pick_the_same_card() {
+        let picks: Vec<usize> = ["2024-06-01", "2024-06-02", "day"]
+            .iter()
+            .map(|x| random_index(Some(x), 1000))
+            .collect();
+        for (seed, pick) in ["2024-06-01", "2024-06-02", "day"].iter().zip(&picks) {
+            assert_eq!(random_index(Some(seed), 1000), *pick);
+        }
+        assert_ne!(picks[0], picks[1]);
+        assert!((0..100).all(|_| random_index(None, 7) < 7));
+        assert_eq!(random_index(Some("anything"), 1), 0);
+    }
+
+    #[test]
+    fn dates_are_utc_days() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_date(951_868_799), "2000-02-29");
+        assert_eq!(utc_date(951_868_800), "2000-03-01");
+        assert_eq!(utc_date(1_735_689_599), "2024-12-31");
+    }
+
+    #[test]
+    fn meta_tags_survive_card_text() {
+        let card = card_with(
+            "quoted",
+            json!({ "description": "Says \"hi\" <script>alert('&')</script>" }),
+        );
+        let template = r#"<meta name="description" content="{description}"><meta property="og:image" content="{ogimage}"><body>{content}</body>"#;
+        let page = PageTemplate::parse(template).unwrap().render(
+            "<p>Rendered</p>",
+            &card.description,
+            "https://example.com/a.png?x=1&y=\"2\"",
+        );
+        assert_eq!(
+            page,
+            r#"<meta name="description" content="Says &quot;hi&quot; &lt;script&gt;alert(&#39;&amp;&#39;)&lt;/script&gt;"><meta property="og:image" content="https://example.com/a.png?x=1&amp;y=&quot;2&quot;"><body><p>Rendered</p></body>"#
+        );
+    }
+
+    fn load(path: &Path) -> Result<(Catalog, LoadReport), CardLoadError> {
+        load_cards(
+            path,
+            Strictness::Off,
+            DuplicatePolicy::KeepFirst,
+            &mut SourceCache::default(),
+        )
+    }
+
+    #[actix_web::test]
+    async fn watcher_reloads_changed_cards() {
+        let dir = env::temp_dir().join(format!("hemolymph-watch-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cards.json");
+        fs::write(&path, json!([card_json("first")]).to_string()).unwrap();
+
+        let (catalog, report) = load(&path).unwrap();
+        let state = web::Data::new(AppState::from_env(
+            Config {
+                cards_path: path.clone(),
+                ..Config::default()
+            },
+            Strictness::Off,
+            catalog,
+            report,
+            true,
+        ));
+        tokio::spawn(watch_cards(state.clone()));
+        // Gives the watcher time to start before the file changes.
+        sleep(Duration::from_millis(500)).await;
+
+        fs::write(
+            &path,
+            json!([card_json("first"), card_json("second")]).to_string(),
+        )
+        .unwrap();
+        let reloaded = tokio::time::timeout(Duration::from_secs(10), async {
+            while state.catalog().await.cards.len() != 2 {
+                sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        fs::remove_dir_all(&dir).ok();
+        assert!(reloaded.is_ok(), "the catalog wasn't reloaded");
+        assert!(state.catalog().await.by_id("second").is_some());
+    }
+
+    #[actix_web::test]
+    async fn admin_reload_counts_the_changes() {
+        let dir = env::temp_dir().join(format!("hemolymph-reload-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cards.json");
+        fs::write(
+            &path,
+            json!([card_json("first"), card_json("gone")]).to_string(),
+        )
+        .unwrap();
+
+        let (catalog, report) = load(&path).unwrap();
+        let mut state = AppState::from_env(
+            Config {
+                cards_path: path.clone(),
+                ..Config::default()
+            },
+            Strictness::Off,
+            catalog,
+            report,
+            true,
+        );
+        state.config.admin_token = Some("secret".to_string());
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/api/admin/reload", web::post().to(admin_reload)),
+        )
+        .await;
+
+        fs::write(
+            &path,
+            json!([card_json("first"), card_json("new")]).to_string(),
+        )
+        .unwrap();
+        let request = || actix_web::test::TestRequest::post().uri("/api/admin/reload");
+        let response = actix_web::test::call_service(&app, request().to_request()).await;
+        assert_eq!(response.status(), 401);
+
+        let response = actix_web::test::call_service(
+            &app,
+            request()
+                .insert_header(("Authorization", "Bearer secret"))
+                .to_request(),
+        )
+        .await;
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(response.status(), 200);
+        let counts: serde_json::Value = actix_web::test::read_body_json(response).await;
+        assert_eq!(
+            (&counts["count"], &counts["added"], &counts["removed"]),
+            (&json!(2), &json!(1), &json!(1))
+        );
+    }
+
+    #[test]
+    fn only_files_of_the_build_are_static() {
+        let script = fs::read_dir("dist")
+            .unwrap()
+            .filter_map(|x| x.ok()?.file_name().into_string().ok())
+            .find(|x| x.ends_with(".js"))
+            .unwrap();
+        assert_eq!(
+            static_file(Path::new("dist"), &format!("/{script}")),
+            Some(fs::canonicalize("dist").unwrap().join(&script))
+        );
+        for path in [
+            "/",
+            "/index.html",
+            "/card/vampire_mantis",
+            "/../Cargo.toml",
+            "/%2e%2e/Cargo.toml",
+            "/%2E%2E%2FCargo.toml",
+            "/dist/../../Cargo.toml",
+            "/..%5CCargo.toml",
+            "//etc/passwd",
+            "/%2Fetc%2Fpasswd",
+            "/C:/Windows/win.ini",
+            &format!("/{script}%00"),
+            "/%FF.js",
+        ] {
+            assert_eq!(static_file(Path::new("dist"), path), None, "{path}");
+        }
+    }
+
+    #[test]
+    fn unacceptable_languages_are_left_out() {
+        let req = actix_web::test::TestRequest::default()
+            .insert_header((header::ACCEPT_LANGUAGE, "fr;q=0, en, es;q=0.5, *"))
+            .to_http_request();
+        assert_eq!(requested_languages(None, &req), ["en", "es"]);
+        assert_eq!(requested_languages(Some("fr"), &req), ["fr"]);
+    }
+
+    #[test]
+    fn site_descriptions_follow_the_languages() {
+        let languages = |x: &[&str]| -> Vec<String> { x.iter().map(ToString::to_string).collect() };
+        assert_eq!(site_description(&[]), SITE_DESCRIPTIONS[0].1);
+        assert_eq!(
+            site_description(&languages(&["ja", "ES-mx", "fr"])),
+            "Un buscador de cartas de Bloodless."
+        );
+        assert_eq!(
+            site_description(&languages(&["ja"])),
+            SITE_DESCRIPTIONS[0].1
+        );
+    }
+
+    #[actix_web::test]
+    async fn maintenance_pauses_the_card_data() {
+        let state = AppState::from_env(
+            Config::default(),
+            Strictness::Off,
+            Catalog::new(HashMap::new()),
+            LoadReport::default(),
+            true,
+        );
+        state.maintenance.store(true, atomic::Ordering::Relaxed);
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/api/catalog", web::get().to(full_catalog))
+                .route("/api/tags", web::get().to(list_tags))
+                .route("/api/deck/parse", web::post().to(parse_deck))
+                .route("/api/ready", web::get().to(ready))
+                .route("/api/health", web::get().to(health)),
+        )
+        .await;
+
+        for (method, path, status) in [
+            ("GET", "/api/catalog", 503),
+            ("GET", "/api/tags", 503),
+            ("POST", "/api/deck/parse", 503),
+            ("GET", "/api/ready", 200),
+            ("GET", "/api/health", 200),
+        ] {
+            let request = actix_web::test::TestRequest::default()
+                .method(method.parse().unwrap())
+                .uri(path)
+                .to_request();
+            let response = actix_web::test::call_service(&app, request).await;
+            assert_eq!(response.status(), status, "{path}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn unknown_pages_are_not_found() {
+        let card: Card = serde_json::from_value(card_json("first")).unwrap();
+        let catalog = Catalog::new(HashMap::from([(card.id.clone(), card)]));
+        let state = AppState::from_env(
+            Config::default(),
+            Strictness::Off,
+            catalog,
+            LoadReport::default(),
+            true,
+        );
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .default_service(web::route().to(serve_index)),
+        )
+        .await;
+
+        for (path, status, description) in [
+            ("/", 200, SITE_DESCRIPTIONS[0].1),
+            ("/search", 200, SITE_DESCRIPTIONS[0].1),
+            ("/card/first", 200, "A test card"),
+            ("/card/missing", 404, "This card wasn't found."),
+            ("/card", 404, "This card wasn't found."),
+            ("/nonsense/first", 404, "Not found."),
+        ] {
+            let request = actix_web::test::TestRequest::get().uri(path).to_request();
+            let response = actix_web::test::call_service(&app, request).await;
+            assert_eq!(response.status(), status, "{path}");
+            let body = actix_web::test::read_body(response).await;
+            let body = String::from_utf8_lossy(&body);
+            assert!(
+                body.contains(&format!("content=\"{}\"", escape_html(description))),
+                "{path}"
+            );
+        }
+    }
+
+    #[actix_web::test]
+    async fn failed_reloads_are_kept() {
+        let dir = env::temp_dir().join(format!("hemolymph-attempt-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cards.json");
+        fs::write(&path, json!([card_json("first")]).to_string()).unwrap();
+        let (catalog, report) = load(&path).unwrap();
+        let state = AppState::from_env(
+            Config {
+                cards_path: path.clone(),
+                admin_token: Some("secret".to_string()),
+                ..Config::default()
+            },
+            Strictness::Off,
+            catalog,
+            report,
+            true,
+        );
+        assert!(state.last_attempt.read().await.is_none());
+
+        fs::write(&path, "[{").unwrap();
+        assert!(state.reload_catalog().await.is_err());
+        let attempt = state.last_attempt.read().await.clone().unwrap();
+        assert!(matches!(attempt.status, ReloadStatus::Failed));
+        assert!(attempt.message.is_some() && attempt.count.is_none());
+
+        fs::write(
+            &path,
+            json!([card_json("second"), {"id": "broken"}]).to_string(),
+        )
+        .unwrap();
+        assert!(state.reload_catalog().await.is_ok());
+        fs::remove_dir_all(&dir).ok();
+        let attempt = state.last_attempt.read().await.clone().unwrap();
+        assert!(matches!(attempt.status, ReloadStatus::Succeeded));
+        assert_eq!(attempt.count, Some(1));
+        assert_eq!(attempt.errors.len(), 1);
+        assert_eq!(attempt.errors[0].id.as_deref(), Some("broken"));
+
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/api/admin/last_reload", web::get().to(last_reload)),
+        )
+        .await;
+        let request = actix_web::test::TestRequest::get()
+            .uri("/api/admin/last_reload")
+            .insert_header((header::AUTHORIZATION, "Bearer secret"))
+            .to_request();
+        let body: serde_json::Value = actix_web::test::call_and_read_body_json(&app, request).await;
+        assert_eq!((&body["version"], &body["count"]), (&json!(2), &json!(1)));
+        assert_eq!(body["attempt"]["status"], "succeeded");
+        assert_eq!(body["attempt"]["errors"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn page_queries_keep_the_first_value() {
+        let pairs = |x: &[(&str, &str)]| -> Vec<(String, String)> {
+            x.iter()
+                .map(|(x, y)| (x.to_string(), y.to_string()))
+                .collect()
+        };
+        let queries = page_queries(pairs(&[
+            ("query", "mantis"),
+            ("sort", ""),
+            ("query", "spider"),
+            ("", "nothing"),
+        ]));
+        assert_eq!(
+            queries,
+            HashMap::from([
+                ("query".to_string(), "mantis".to_string()),
+                ("sort".to_string(), String::new()),
+            ])
+        );
+    }
+
+    #[test]
+    fn search_pages_are_rendered_with_their_query() {
+        let req = actix_web::test::TestRequest::get()
+            .uri("/search?query=zyxwvut&sort=name&query=other")
+            .to_http_request();
+        assert_eq!(
+            PageProps::new(&req),
+            PageProps {
+                url: "/search".to_string(),
+                queries: HashMap::from([
+                    ("query".to_string(), "zyxwvut".to_string()),
+                    ("sort".to_string(), "name".to_string()),
+                ]),
+            }
+        );
+    }
+
+    #[test]
+    fn search_previews_count_the_matches() {
+        let cards = ["first", "second"].map(|x| {
+            let card = card_with(x, json!({ "kins": [format!("{x}_kin")] }));
+            (card.id.clone(), card)
+        });
+        let mut catalog = Catalog::new(HashMap::from(cards));
+        catalog.kins = KinHierarchy::from_links(HashMap::from([
+            ("first_kin".to_string(), "broad_kin".to_string()),
+            ("second_kin".to_string(), "broad_kin".to_string()),
+        ]))
+        .0;
+        let preview = |query: &str| {
+            let params = web::Query::<QueryParams>::from_query(query).unwrap();
+            search_preview(&catalog, params.into_inner(), "https://example.com")
+        };
+
+        let (description, image) = preview("name=first").unwrap();
+        assert!(description.starts_with("1 card where "), "{description}");
+        assert_eq!(image, "https://example.com/first.png");
+        let (description, image) = preview("query=test").unwrap();
+        assert!(description.starts_with("2 cards where "), "{description}");
+        assert!(image.is_empty());
+        assert!(preview("").is_none());
+        assert!(preview("regex=(").is_none());
+        let (description, _) = preview("kin=broad_kin").unwrap();
+        assert!(description.starts_with("2 cards where "), "{description}");
+
+        let (description, _) = preview(&format!("name={}", "a".repeat(500))).unwrap();
+        assert!(description.ends_with('…') && description.chars().count() < 150);
+    }
+
+    #[test]
+    fn meta_descriptions_are_one_short_line() {
+        assert_eq!(
+            meta_description("  Deal 2 damage.\nThen heal.  "),
+            "Deal 2 damage. Then heal."
+        );
+        let long = "word ".repeat(60);
+        let description = meta_description(&long);
+        assert!(description.ends_with("word…"), "{description}");
+        assert!(description.chars().count() <= MAX_META_DESCRIPTION + 1);
+        let description = meta_description(&"a".repeat(300));
+        assert_eq!(description.chars().count(), MAX_META_DESCRIPTION + 1);
+        let exact = "b".repeat(MAX_META_DESCRIPTION);
+        assert_eq!(meta_description(&exact), exact);
+
+        let page = PageTemplate::parse("<meta content=\"{description}\">{content}{ogimage}")
+            .unwrap()
+            .render("", "<b>\"Bold\"</b>\n& more", "");
+        assert_eq!(
+            page,
+            "<meta content=\"&lt;b&gt;&quot;Bold&quot;&lt;/b&gt; &amp; more\">"
+        );
+    }
+
+    #[actix_web::test]
+    async fn catalog_lines_have_one_card_each() {
+        let cards = ["b", "a"].map(|x| {
+            let card: Card = serde_json::from_value(card_json(x)).unwrap();
+            (card.id.clone(), card)
+        });
+        let state = AppState::from_env(
+            Config::default(),
+            Strictness::Off,
+            Catalog::new(HashMap::from(cards)),
+            LoadReport::default(),
+            true,
+        );
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/api/catalog.jsonl", web::get().to(catalog_lines)),
+        )
+        .await;
+        let request = actix_web::test::TestRequest::get()
+            .uri("/api/catalog.jsonl")
+            .to_request();
+        let body = actix_web::test::call_and_read_body(&app, request).await;
+        let ids: Vec<String> = String::from_utf8(body.to_vec())
+            .unwrap()
+            .lines()
+            .map(|x| serde_json::from_str::<Card>(x).unwrap().id)
+            .collect();
+        assert_eq!(ids, ["a", "b"]);
+    }
+
+    #[actix_web::test]
+    async fn missing_cards_are_not_found() {
+        let state = AppState::from_env(
+            Config::default(),
+            Strictness::Off,
+            Catalog::new(HashMap::new()),
+            LoadReport::default(),
+            true,
+        );
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(state))
+                .route("/api/card", web::get().to(view_card)),
+        )
+        .await;
+        let request = actix_web::test::TestRequest::get()
+            .uri("/api/card?id=missing")
+            .to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+        assert_eq!(response.status(), 404);
+    }
+
+    #[test]
+    fn templates_need_every_placeholder() {
+        let template =
+            PageTemplate::parse("<title>{description}</title>{ogimage}{content}|{content}")
+                .unwrap();
+        assert_eq!(
+            template.render("<p>{description}</p>", "A & B", "a.png"),
+            "<title>A &amp; B</title>a.png<p>{description}</p>|<p>{description}</p>"
+        );
+        let error = PageTemplate::parse("<body>{content}</body>").err().unwrap();
+        assert_eq!(error, "doesn't have {description}, {ogimage}");
+    }
+
+    #[test]
+    fn catalogs_that_cant_load_say_why() {
+        let dir = env::temp_dir().join(format!("hemolymph-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cards.json");
+
+        let error = load(&path).err().unwrap();
+        assert!(error.is_not_found());
+        fs::write(&path, "{}").unwrap();
+        let error = load(&path).err().unwrap();
+        assert!(matches!(error, CardLoadError::Parse(..)));
+        assert!(error.to_string().contains("isn't an array of cards"));
+        fs::write(&path, "[]").unwrap();
+        let error = load(&path).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("{} has no cards", path.display())
+        );
+
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(load(&dir), Err(CardLoadError::Empty(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn set_order_is_read_next_to_the_cards() {
+        let dir = env::temp_dir().join(format!("hemolymph-sets-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cards.json");
+        fs::write(&path, json!([card_json("first")]).to_string()).unwrap();
+        let sets = dir.join("sets.json");
+
+        let (catalog, report) = load(&path).unwrap();
+        assert!(catalog.set_order.is_empty());
+        assert!(!report.warnings.iter().any(|x| x.contains("sets.json")));
+        fs::write(&sets, r#"{"Test": 2, "Older": 1}"#).unwrap();
+        let (catalog, _) = load(&path).unwrap();
+        assert_eq!(catalog.set_order["Test"], 2);
+        fs::write(&sets, "{").unwrap();
+        let (catalog, report) = load(&path).unwrap();
+        assert_eq!(catalog.cards.len(), 1);
+        assert!(catalog.set_order.is_empty());
+        assert!(report.warnings.iter().any(|x| x.contains("sets.json")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn several_card_files_are_merged() {
+        let dir = env::temp_dir().join(format!("hemolymph-merge-{}", std::process::id()));
+        fs::create_dir_all(dir.join("playtest")).unwrap();
+        let main = dir.join("main.json");
+        let playtest = dir.join("playtest").join("new.json");
+        let mut changed = card_json("shared");
+        changed["name"] = json!("Changed");
+        fs::write(
+            &main,
+            json!([card_json("first"), card_json("shared")]).to_string(),
+        )
+        .unwrap();
+        fs::write(&playtest, json!([changed, card_json("new")]).to_string()).unwrap();
+        let spec = PathBuf::from(format!(
+            "{}, {}",
+            main.display(),
+            dir.join("play*").join("*.json").display()
+        ));
+        let load = |duplicates, cache: &mut SourceCache| {
+            load_cards(&spec, Strictness::Off, duplicates, cache)
+        };
+
+        let mut cache = SourceCache::default();
+        let (catalog, report) = load(DuplicatePolicy::KeepFirst, &mut cache).unwrap();
+        assert_eq!(catalog.cards.len(), 3);
+        assert_eq!(catalog.cards["shared"].name, "shared");
+        assert!(report.errors[0].message.contains("main.json"));
+
+        let (catalog, report) = load(DuplicatePolicy::KeepLast, &mut cache).unwrap();
+        assert_eq!(catalog.cards["shared"].name, "Changed");
+        assert!(report.errors.is_empty() && report.warnings.iter().any(|x| x.contains("replaces")));
+        assert!(matches!(
+            load(DuplicatePolicy::Reject, &mut cache),
+            Err(CardLoadError::DuplicateId(x)) if x == "shared"
+        ));
+
+        // A file that breaks keeps its cards from the last read.
+        fs::write(&playtest, "[").unwrap();
+        let (catalog, report) = load(DuplicatePolicy::KeepFirst, &mut cache).unwrap();
+        assert!(catalog.cards.contains_key("new"));
+        assert!(report.warnings.iter().any(|x| x.contains("last read")));
+        assert!(load(DuplicatePolicy::KeepFirst, &mut SourceCache::default()).is_err());
+
+        assert_eq!(
+            watched_paths(&spec),
+            [main.clone(), dir.clone()],
+            "globs are watched from the folder before their first wildcard"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
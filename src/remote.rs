@@ -0,0 +1,145 @@
+use std::path::Path;
+use std::time::Duration;
+
+use reqwest::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::{Client, StatusCode};
+
+/// Longest a fetch of the cards can take before it fails.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Whether the cards of `spec` are fetched instead of read from files.
+pub fn is_url(spec: &Path) -> bool {
+    let spec = spec.to_string_lossy();
+    spec.starts_with("http://") || spec.starts_with("https://")
+}
+
+/// Cards served over HTTP, with what's needed to only download them again when they change.
+pub struct Remote {
+    client: Client,
+    url: String,
+    /// The latest cards downloaded, and the validators their response came with.
+    body: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+/// The cards at the URL as of a fetch.
+pub struct Fetched {
+    pub body: String,
+    /// Whether they differ from the previous fetch.
+    pub changed: bool,
+}
+
+impl Remote {
+    pub fn new(url: String) -> Result<Self, String> {
+        let client = Client::builder()
+            .timeout(TIMEOUT)
+            .user_agent(concat!("hemolymph-rs/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|x| format!("Unable to fetch {url}: {x}"))?;
+        Ok(Self {
+            client,
+            url,
+            body: None,
+            etag: None,
+            last_modified: None,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Downloads the cards, unless the server says they haven't changed since the last fetch, in
+    /// which case the ones from then are returned. Fails on network errors and error statuses.
+    pub async fn fetch(&mut self) -> Result<Fetched, String> {
+        let fail = |x: reqwest::Error| format!("Unable to fetch {}: {x}", self.url);
+        let mut request = self.client.get(&self.url);
+        if self.body.is_some() {
+            if let Some(etag) = &self.etag {
+                request = request.header(IF_NONE_MATCH, etag);
+            }
+            if let Some(date) = &self.last_modified {
+                request = request.header(IF_MODIFIED_SINCE, date);
+            }
+        }
+        let response = request.send().await.map_err(fail)?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            if let Some(body) = &self.body {
+                return Ok(Fetched {
+                    body: body.clone(),
+                    changed: false,
+                });
+            }
+        }
+
+        let response = response.error_for_status().map_err(fail)?;
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(str::to_string)
+        };
+        let (etag, last_modified) = (header(ETAG), header(LAST_MODIFIED));
+        let body = response.text().await.map_err(fail)?;
+        // Servers without validators send everything every time, which may still be the same.
+        let changed = self.body.as_ref() != Some(&body);
+        self.body = Some(body.clone());
+        (self.etag, self.last_modified) = (etag, last_modified);
+        Ok(Fetched { body, changed })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn unchanged_cards_arent_downloaded_again() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut conditional = vec![];
+            for (stream, body) in listener.incoming().zip(["[1]", "", "[2]"]) {
+                let mut stream = stream.unwrap();
+                let mut lines = BufReader::new(&stream).lines().map(Result::unwrap);
+                let headers: Vec<String> = lines.by_ref().take_while(|x| !x.is_empty()).collect();
+                conditional.push(
+                    headers
+                        .iter()
+                        .any(|x| x.to_ascii_lowercase().starts_with("if-none-match: \"one\"")),
+                );
+                let response = if body.is_empty() {
+                    "HTTP/1.1 304 Not Modified\r\nConnection: close\r\n\r\n".to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\nETag: \"one\"\r\nContent-Length: {}\r\n\
+                         Connection: close\r\n\r\n{body}",
+                        body.len()
+                    )
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            conditional
+        });
+
+        let url = format!("http://{address}/cards.json");
+        assert!(is_url(Path::new(&url)) && !is_url(Path::new("cards.json")));
+        let mut remote = Remote::new(url).unwrap();
+        let first = remote.fetch().await.unwrap();
+        assert_eq!((first.body.as_str(), first.changed), ("[1]", true));
+        let second = remote.fetch().await.unwrap();
+        assert_eq!((second.body.as_str(), second.changed), ("[1]", false));
+        let third = remote.fetch().await.unwrap();
+        assert_eq!((third.body.as_str(), third.changed), ("[2]", true));
+        assert_eq!(server.join().unwrap(), [false, true, true]);
+
+        let mut gone = Remote::new(format!("http://{address}/cards.json")).unwrap();
+        assert!(gone.fetch().await.is_err());
+    }
+}
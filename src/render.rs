@@ -0,0 +1,102 @@
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::thread;
+
+use tokio::runtime::Builder;
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::task::{self, LocalSet};
+
+/// Renders a page. Server rendering makes futures that can't leave their thread, so a job only
+/// makes its future once it's on a worker.
+type Job = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = String>>> + Send>;
+
+/// A job and where its page is sent.
+type Queued = (Job, oneshot::Sender<Result<String, String>>);
+
+/// Pages that can wait for a worker before new ones wait to be queued.
+const QUEUE_SIZE: usize = 64;
+
+/// A fixed number of threads that render pages, each with its own runtime, so pages are rendered
+/// without building a runtime for each of them and a burst of page loads waits for a worker
+/// instead of starting more threads.
+pub struct RenderPool {
+    jobs: mpsc::Sender<Queued>,
+}
+
+impl RenderPool {
+    /// Starts `threads` workers, or one if it's 0. They stop once the pool is dropped.
+    pub fn new(threads: usize) -> io::Result<Self> {
+        let (tx, rx) = mpsc::channel(QUEUE_SIZE);
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..threads.max(1) {
+            let rx = Arc::clone(&rx);
+            let runtime = Builder::new_current_thread().enable_all().build()?;
+            thread::Builder::new()
+                .name(format!("render-{i}"))
+                .spawn(move || {
+                    LocalSet::new().block_on(&runtime, work(&rx));
+                })?;
+        }
+        Ok(Self { jobs: tx })
+    }
+
+    /// Renders a page on a worker with the future `job` makes. Fails if rendering panics.
+    pub async fn render<F, Fut>(&self, job: F) -> Result<String, String>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = String> + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move || Box::pin(job()));
+        self.jobs
+            .send((job, tx))
+            .await
+            .map_err(|_| "The renderers stopped".to_string())?;
+        rx.await
+            .map_err(|_| "The renderer stopped before finishing the page".to_string())?
+    }
+}
+
+/// Renders pages one at a time until the pool is dropped.
+async fn work(jobs: &Mutex<mpsc::Receiver<Queued>>) {
+    loop {
+        let Some((job, reply)) = jobs.lock().await.recv().await else {
+            return;
+        };
+        // Spawned so a panic fails the page instead of the worker.
+        let page = task::spawn_local(job()).await.map_err(|x| x.to_string());
+        let _ = reply.send(page);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn failed_pages_keep_the_worker() {
+        let pool = RenderPool::new(1).unwrap();
+        // Futures that can't be sent between threads are the point of the pool.
+        let page = pool
+            .render(|| async {
+                let text = Rc::new("page".to_string());
+                task::yield_now().await;
+                text.to_string()
+            })
+            .await;
+        assert_eq!(page.unwrap(), "page");
+
+        assert!(pool
+            .render(|| async { panic!("broken page") })
+            .await
+            .is_err());
+        assert_eq!(
+            pool.render(|| async { "again".to_string() }).await.unwrap(),
+            "again"
+        );
+    }
+}
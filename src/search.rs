@@ -1,14 +1,455 @@
 pub mod query_parser;
-use serde::Deserialize;
 
-use crate::cards::{Card, Keyword};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::ops::{Bound, Range};
 
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize, Serializer};
+use unicode_normalization::UnicodeNormalization;
+
+use rayon::prelude::*;
+
+use crate::cards::{fold, fold_chars, is_iso_date, normalize_text, Card, CardFace, Keyword, Stat};
+use crate::kins::KinHierarchy;
+
+use self::query_parser::{query_parser, text_comparison_parser, QUERY_PARAMS};
+
+/// Parameters accepted by `/api/search`.
 #[derive(Deserialize)]
 pub struct QueryParams {
+    /// Query text in the syntax of [`query_parser`]. Words that aren't `param:value` pairs are
+    /// matched fuzzily against the card's name, description, type, kins and keywords. A missing
+    /// query matches every card.
     pub query: Option<String>,
+    /// Only keep cards with a name containing this text. A `*` at the start or end makes it match
+    /// only names ending or starting with the rest, like `Blood*`.
+    pub name: Option<String>,
+    /// How `name` is matched. Wildcards are only read with the default, `contains`.
+    #[serde(default)]
+    pub match_mode: NameMatch,
+    /// Only keep cards whose name or description matches this regular expression.
+    pub regex: Option<String>,
+    /// Only keep cards whose cost satisfies a comparison like `3`, `>=3`, `<5` or `2-4`.
+    pub cost: Option<String>,
+    /// Only keep cards whose power satisfies a comparison, written like `cost`.
+    pub power: Option<String>,
+    /// Only keep cards whose health satisfies a comparison, written like `cost`.
+    pub health: Option<String>,
+    /// Only keep cards whose defense satisfies a comparison, written like `cost`.
+    pub defense: Option<String>,
+    /// Only keep cards of this rarity, ignoring case.
+    pub rarity: Option<String>,
+    /// Only keep cards that can be played in this format.
+    pub legal_in: Option<String>,
+    /// Only keep cards released on or after this `YYYY-MM-DD` date.
+    pub released_after: Option<String>,
+    /// Only keep cards released on or before this `YYYY-MM-DD` date.
+    pub released_before: Option<String>,
+    /// Only keep cards with Devours that could devour the card with this name.
+    pub devours: Option<String>,
+    /// Only keep cards of this kin or whose text mentions it, to build around a kin.
+    pub synergy: Option<String>,
+    /// Only keep cards with every one of these kins, separated by commas, like `bug,undead`.
+    pub kins_all: Option<String>,
+    /// Only keep cards with at least one of these kins, separated by commas.
+    pub kins_any: Option<String>,
+    /// Only keep cards with this one among their `other` tags, ignoring case.
+    pub other: Option<String>,
+    /// Let `other` match tags that only contain it.
+    #[serde(default)]
+    pub other_substring: bool,
+    /// Language to show card text in. Translations are also searched when it's given.
+    pub lang: Option<String>,
+    /// How to order the results, also accepted as `order`. When it's missing, results for a query
+    /// with text are ordered by [`Query::score`], best first, and other results are unordered.
+    #[serde(alias = "order")]
+    pub sort: Option<SortOrder>,
+    /// Also return where the query matched the names and descriptions of each card.
+    #[serde(default)]
+    pub highlight: bool,
+    /// How the query text is matched against names and descriptions.
+    #[serde(default)]
+    pub text_mode: TextMode,
+    /// Whether cards need `all` the words of the query text, the default, or `any` of them. Also
+    /// accepted as `match`.
+    #[serde(default, alias = "match")]
+    pub word_match: WordMatch,
+    /// Let the query text also match names with typos, ranking the closest names first. Slower
+    /// than the default substring search.
+    #[serde(default)]
+    pub fuzzy: bool,
+    /// Whether the results can come from, and are kept in, the search cache. `cache=false` always
+    /// searches again, for debugging.
+    #[serde(default = "yes")]
+    pub cache: bool,
+    /// Most typos a fuzzy match can have. Defaults to [`typo_limit`] of the query text.
+    pub max_distance: Option<usize>,
+    /// Id of the last card of the previous page. Pages are ordered by id, so they stay consistent
+    /// when the catalog is reloaded between requests.
+    pub cursor: Option<String>,
+    /// Number of cards per page. Defaults to [`DEFAULT_PAGE_SIZE`] when only `cursor` is given.
+    pub limit: Option<usize>,
+    /// Only send these fields of each card, separated by commas, like `id,name,cost`.
+    pub fields: Option<String>,
+    /// Leave out cards of these types, given as `exclude_type` as many times as needed. Read by
+    /// [`QueryParams::read_repeated`], since repeated parameters can't be deserialized.
+    #[serde(skip)]
+    pub exclude_type: Vec<String>,
+    /// Leave out cards with these kins or any kin within them, given as `exclude_kin` as many
+    /// times as needed.
+    #[serde(skip)]
+    pub exclude_kin: Vec<String>,
+    /// Only keep cards by any of these artists, ignoring case, given as `artist` as many times
+    /// as needed.
+    #[serde(skip)]
+    pub artist: Vec<String>,
+    /// Only keep cards from any of these sets, ignoring case, given as `set`.
+    #[serde(skip)]
+    pub set: Vec<String>,
+    /// Only keep cards with any of these keywords, ignoring case, given as `keyword`.
+    #[serde(skip)]
+    pub keyword: Vec<String>,
 }
 
-#[derive(Deserialize)]
+fn yes() -> bool {
+    true
+}
+
+/// Cards per page when paginating without a `limit`.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+impl QueryParams {
+    /// Whether the results are split in pages, which happens when `cursor` or `limit` is given.
+    pub fn is_paginated(&self) -> bool {
+        self.cursor.is_some() || self.limit.is_some()
+    }
+
+    /// Number of cards per page, if the results are paginated.
+    pub fn page_size(&self) -> Option<usize> {
+        self.is_paginated()
+            .then(|| self.limit.unwrap_or(DEFAULT_PAGE_SIZE).max(1))
+    }
+
+    /// Reads the parameters that can be given more than once from every `key=value` pair of the
+    /// query string.
+    pub fn read_repeated(&mut self, pairs: &[(String, String)]) {
+        for (key, value) in pairs.iter().filter(|(_, x)| !x.trim().is_empty()) {
+            match key.as_str() {
+                "exclude_type" => self.exclude_type.push(value.clone()),
+                "exclude_kin" => self.exclude_kin.push(value.clone()),
+                "artist" => self.artist.push(value.trim().to_string()),
+                "set" => self.set.push(value.trim().to_string()),
+                "keyword" => self.keyword.push(value.trim().to_string()),
+                _ => (),
+            }
+        }
+    }
+
+    /// The `artist`, `set` and `keyword` filters that were given, with their values.
+    pub fn applied_filters(&self) -> BTreeMap<&'static str, &[String]> {
+        [
+            ("artist", self.artist.as_slice()),
+            ("set", self.set.as_slice()),
+            ("keyword", self.keyword.as_slice()),
+        ]
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .collect()
+    }
+
+    /// Rewrites every text parameter with [`normalize_text`], so text pasted with other Unicode
+    /// forms, typographic quotes or extra spaces still matches the cards. Regexes only get the
+    /// Unicode form, since their spaces, dashes and quotes mean what they were written as.
+    pub fn normalize(&mut self) {
+        if let Some(regex) = &mut self.regex {
+            *regex = regex.nfc().collect();
+        }
+        let single = [
+            &mut self.query,
+            &mut self.name,
+            &mut self.rarity,
+            &mut self.legal_in,
+            &mut self.devours,
+            &mut self.synergy,
+            &mut self.kins_all,
+            &mut self.kins_any,
+            &mut self.other,
+        ];
+        for value in single.into_iter().flatten() {
+            *value = normalize_text(value);
+        }
+        let repeated = [
+            &mut self.exclude_type,
+            &mut self.exclude_kin,
+            &mut self.artist,
+            &mut self.set,
+            &mut self.keyword,
+        ];
+        for value in repeated.into_iter().flatten() {
+            *value = normalize_text(value);
+        }
+    }
+
+    /// Parses the query text together with every filter given as its own parameter.
+    pub fn parse(&self) -> Result<Query, Errors> {
+        let mut query = query_parser(self.query.as_deref().unwrap_or_default())?;
+        if let Some(name) = &self.name {
+            query
+                .restrictions
+                .push(QueryRestriction::Name(NamePattern::with_mode(
+                    name,
+                    self.match_mode,
+                )));
+        }
+        if let Some(pattern) = &self.regex {
+            query
+                .restrictions
+                .push(QueryRestriction::Regex(compile_regex(pattern)?));
+        }
+        let stats = [
+            (NumberProperty::Cost, &self.cost),
+            (NumberProperty::Power, &self.power),
+            (NumberProperty::Health, &self.health),
+            (NumberProperty::Defense, &self.defense),
+        ];
+        for (property, comparison) in stats {
+            if let Some(comparison) = comparison {
+                query.restrictions.push(QueryRestriction::Comparison(
+                    property,
+                    text_comparison_parser(comparison)?,
+                ));
+            }
+        }
+        if let Some(kin) = self.synergy.as_deref().map(str::trim) {
+            if !kin.is_empty() {
+                query
+                    .restrictions
+                    .push(QueryRestriction::Synergy(kin.to_string()));
+            }
+        }
+        if let Some(rarity) = &self.rarity {
+            query
+                .restrictions
+                .push(QueryRestriction::Rarity(rarity.clone()));
+        }
+        if let Some(format) = &self.legal_in {
+            query
+                .restrictions
+                .push(QueryRestriction::LegalIn(format.clone()));
+        }
+        if let Some(date) = &self.released_after {
+            if !is_iso_date(date) {
+                return Err(Errors::InvalidDate);
+            }
+            query
+                .restrictions
+                .push(QueryRestriction::ReleasedAfter(date.clone()));
+        }
+        if let Some(date) = &self.released_before {
+            if !is_iso_date(date) {
+                return Err(Errors::InvalidDate);
+            }
+            query
+                .restrictions
+                .push(QueryRestriction::ReleasedBefore(date.clone()));
+        }
+        // Values of the same filter are alternatives, but every filter has to match.
+        let artists = self
+            .artist
+            .iter()
+            .map(|x| QueryRestriction::Has(ListProperty::Artists, x.clone()));
+        let sets = self
+            .set
+            .iter()
+            .map(|x| QueryRestriction::Is(TextProperty::Set, x.clone()));
+        let keywords = self
+            .keyword
+            .iter()
+            .map(|x| QueryRestriction::HasKw(x.clone()));
+        for mut any in [
+            artists.collect::<Vec<_>>(),
+            sets.collect(),
+            keywords.collect(),
+        ] {
+            match any.len() {
+                0 => (),
+                1 => query.restrictions.append(&mut any),
+                _ => query.restrictions.push(QueryRestriction::AnyOf(any)),
+            }
+        }
+        let kins = |kins: Option<&str>| -> Vec<QueryRestriction> {
+            kins.into_iter()
+                .flat_map(|x| x.split(','))
+                .map(str::trim)
+                .filter(|x| !x.is_empty())
+                .map(|x| QueryRestriction::Has(ListProperty::Kins, x.to_string()))
+                .collect()
+        };
+        query.restrictions.extend(kins(self.kins_all.as_deref()));
+        let mut any_kin = kins(self.kins_any.as_deref());
+        match any_kin.len() {
+            0 => (),
+            1 => query.restrictions.append(&mut any_kin),
+            _ => query.restrictions.push(QueryRestriction::AnyOf(any_kin)),
+        }
+        if let Some(tag) = &self.other {
+            query.restrictions.push(if self.other_substring {
+                QueryRestriction::HasPart(ListProperty::Other, tag.clone())
+            } else {
+                QueryRestriction::Has(ListProperty::Other, tag.clone())
+            });
+        }
+        for kind in &self.exclude_type {
+            query
+                .restrictions
+                .push(QueryRestriction::Not(Box::new(QueryRestriction::Contains(
+                    TextProperty::Type,
+                    kind.clone(),
+                ))));
+        }
+        for kin in &self.exclude_kin {
+            query
+                .restrictions
+                .push(QueryRestriction::Not(Box::new(QueryRestriction::Has(
+                    ListProperty::Kins,
+                    kin.clone(),
+                ))));
+        }
+        for restriction in &mut query.restrictions {
+            let QueryRestriction::Fuzzy(text) = restriction else {
+                continue;
+            };
+            match (self.text_mode, self.word_match) {
+                (TextMode::Words, word_match) => {
+                    let mut words = tokenize(text);
+                    if words.iter().any(|x| !is_stop_word(x)) {
+                        words.retain(|x| !is_stop_word(x));
+                    }
+                    let words: Vec<String> = words.iter().map(|x| stem(x).to_string()).collect();
+                    if !words.is_empty() {
+                        *restriction = QueryRestriction::Words(words, word_match);
+                    }
+                }
+                (TextMode::Substring, WordMatch::Any) if !text.is_empty() => {
+                    *restriction = QueryRestriction::FuzzyAny(std::mem::take(text));
+                }
+                (TextMode::Substring, _) => (),
+            }
+        }
+        if self.fuzzy {
+            query.allow_typos(self.max_distance);
+        }
+        Ok(query)
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TextMode {
+    /// The text can appear anywhere, even inside a word, so `art` matches `heart`.
+    #[default]
+    Substring,
+    /// Every word of the text must be a whole word of the card, or the start of one, as split by
+    /// [`tokenize`].
+    Words,
+}
+
+/// How the `name` parameter is matched against names, ignoring case.
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NameMatch {
+    /// The text can appear anywhere in the name.
+    #[default]
+    Contains,
+    /// The name starts with the text, for jumping to a card while typing its name.
+    Prefix,
+    /// The name is the text.
+    Exact,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum WordMatch {
+    #[default]
+    All,
+    Any,
+}
+
+/// Why a card matched `synergy`.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SynergyMatch {
+    /// The card is of the kin.
+    Membership,
+    /// The card's description or abilities mention the kin.
+    Text,
+}
+
+/// Whether the card is of `kin` or mentions it, ignoring case and accents. Mentions are words
+/// that start with the kin, so plurals count too.
+pub fn synergy(card: &Card, kin: &str) -> Option<SynergyMatch> {
+    let kin = fold(kin);
+    let text = card.search_text();
+    if text.kins.split('\n').any(|x| x == kin) {
+        return Some(SynergyMatch::Membership);
+    }
+    let faces = text.faces.iter().map(|(_, description)| description);
+    [&text.description, &text.abilities]
+        .into_iter()
+        .chain(faces)
+        .any(|x| {
+            word_ranges(x)
+                .into_iter()
+                .any(|word| x[word].starts_with(&kin))
+        })
+        .then_some(SynergyMatch::Text)
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    /// By set, then in the order cards appear in it.
+    CollectorNumber,
+    /// Oldest first. Cards without a release date go last.
+    Released,
+    /// Best matches for the query text first, as scored by [`relevance`].
+    Relevance,
+}
+
+/// How well a card matches some query text, higher being better: 4 when a name is the text, 3
+/// when a name starts with it, 2 when a name contains it, 1 when only the description does and 0
+/// otherwise. Case is ignored.
+pub fn relevance(card: &Card, text: &str) -> u8 {
+    let text = fold(text.trim());
+    if text.is_empty() {
+        return 0;
+    }
+    let name_score = card
+        .names()
+        .map(|name| {
+            let name = fold(name);
+            if name == text {
+                4
+            } else if name.starts_with(&text) {
+                3
+            } else if name.contains(&text) {
+                2
+            } else {
+                0
+            }
+        })
+        .max()
+        .unwrap_or(0);
+    let description_score = u8::from(
+        std::iter::once(&card.description)
+            .chain(card.faces.iter().map(|x| &x.description))
+            .any(|x| fold(x).contains(&text)),
+    );
+    name_score.max(description_score)
+}
+
+#[derive(Deserialize, Serialize, Debug)]
 pub enum Comparison {
     GreaterThan(usize),
     GreaterThanOrEqual(usize),
@@ -16,43 +457,1975 @@ pub enum Comparison {
     Equal(usize),
     LowerThan(usize),
     NotEqual(usize),
+    /// Written `2-4`. Both ends are included, and nothing matches when the start is past the end.
+    Between(usize, usize),
 }
 
 impl Comparison {
-    pub fn compare<T: PartialOrd<usize>>(&self, a: &T) -> bool {
+    /// Only fixed stats can be compared, so variable and absent stats never match.
+    pub fn compare(&self, a: Stat) -> bool {
+        let Stat::Fixed(a) = a else {
+            return false;
+        };
+        match self {
+            Comparison::GreaterThan(x) => a > *x,
+            Comparison::Equal(x) => a == *x,
+            Comparison::LowerThan(x) => a < *x,
+            Comparison::NotEqual(x) => a != *x,
+            Comparison::GreaterThanOrEqual(x) => a >= *x,
+            Comparison::LowerThanOrEqual(x) => a <= *x,
+            Comparison::Between(start, end) => (*start..=*end).contains(&a),
+        }
+    }
+
+    /// Compares a signed number. Negative numbers are lower than every bound.
+    pub fn compare_number(&self, a: i64) -> bool {
+        match usize::try_from(a) {
+            Ok(a) => self.compare(Stat::Fixed(a)),
+            Err(_) => matches!(
+                self,
+                Comparison::LowerThan(_)
+                    | Comparison::LowerThanOrEqual(_)
+                    | Comparison::NotEqual(_)
+            ),
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Comparison::GreaterThan(x) => a > x,
-            Comparison::Equal(x) => a == x,
-            Comparison::LowerThan(x) => a < x,
-            Comparison::NotEqual(x) => a != x,
-            Comparison::GreaterThanOrEqual(x) => a >= x,
-            Comparison::LowerThanOrEqual(x) => a <= x,
+            Comparison::GreaterThan(x) => write!(f, "greater than {x}"),
+            Comparison::GreaterThanOrEqual(x) => write!(f, "greater than or equal to {x}"),
+            Comparison::LowerThanOrEqual(x) => write!(f, "lower than or equal to {x}"),
+            Comparison::Equal(x) => write!(f, "{x}"),
+            Comparison::LowerThan(x) => write!(f, "lower than {x}"),
+            Comparison::NotEqual(x) => write!(f, "not {x}"),
+            Comparison::Between(start, end) => write!(f, "between {start} and {end}"),
         }
     }
 }
 
+#[derive(Debug)]
 pub enum Errors {
     InvalidComparisonString,
-    UnknownParam,
+    /// A `param:value` pair with a param the query syntax doesn't have.
+    UnknownParam(String),
+    InvalidDate,
+    InvalidRegex(String),
+}
+
+impl Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Errors::InvalidComparisonString => {
+                write!(f, "comparisons are written like 3, >=3, <5 or 2-4")
+            }
+            Errors::UnknownParam(param) => write!(
+                f,
+                "there's no \"{param}:\" parameter, the valid ones are {}",
+                QUERY_PARAMS.join(", ")
+            ),
+            Errors::InvalidDate => write!(f, "dates are written like YYYY-MM-DD"),
+            Errors::InvalidRegex(message) => write!(f, "invalid regex: {message}"),
+        }
+    }
+}
+
+/// Longest regular expression accepted from users.
+const MAX_REGEX_LENGTH: usize = 256;
+
+/// Limit in bytes for the compiled form of user regular expressions. The regex crate matches in
+/// linear time, so capping the size of the pattern is enough to bound the work of a search.
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+fn compile_regex(pattern: &str) -> Result<Regex, Errors> {
+    if pattern.chars().count() > MAX_REGEX_LENGTH {
+        return Err(Errors::InvalidRegex(format!(
+            "The pattern is longer than {MAX_REGEX_LENGTH} characters"
+        )));
+    }
+    RegexBuilder::new(pattern)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|x| Errors::InvalidRegex(x.to_string()))
+}
+
+/// Byte ranges of the words of `text`, which are its runs of letters and digits.
+pub fn word_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(word)) => {
+                ranges.push(word..i);
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(word) = start {
+        ranges.push(word..text.len());
+    }
+    ranges
+}
+
+/// Splits `text` in [`fold`]ed words, breaking it at everything that isn't a letter or a digit.
+pub fn tokenize(text: &str) -> Vec<String> {
+    word_ranges(text)
+        .into_iter()
+        .map(|x| fold(&text[x]))
+        .collect()
+}
+
+/// Byte ranges of every place where `text` contains `needle`, ignoring case and accents. `needle`
+/// must be [`fold`]ed already. The ranges don't overlap.
+pub fn find_ignoring_case(text: &str, needle: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    if needle.is_empty() {
+        return ranges;
+    }
+    let mut start = 0;
+    while let Some(c) = text[start..].chars().next() {
+        match match_at(text, start, needle) {
+            Some(end) => {
+                ranges.push(start..end);
+                start = end;
+            }
+            None => start += c.len_utf8(),
+        }
+    }
+    ranges
+}
+
+/// Where a match of the folded `needle` that begins at `start` of `text` ends, if there's one.
+fn match_at(text: &str, start: usize, needle: &str) -> Option<usize> {
+    let mut needle = needle.chars();
+    for (i, c) in text[start..].char_indices() {
+        for folded in fold_chars(std::iter::once(c)) {
+            if needle.next() != Some(folded) {
+                return None;
+            }
+        }
+        if needle.as_str().is_empty() {
+            return Some(start + i + c.len_utf8());
+        }
+    }
+    None
+}
+
+/// Most typos allowed by `fuzzy=true` when `max_distance` isn't given.
+pub const DEFAULT_MAX_DISTANCE: usize = 2;
+
+/// Typos allowed in `text` when `max_distance` isn't given: one for every four characters, at
+/// least one and at most [`DEFAULT_MAX_DISTANCE`].
+pub fn typo_limit(text: &str) -> usize {
+    (text.chars().count() / 4).clamp(1, DEFAULT_MAX_DISTANCE)
+}
+
+/// Damerau-Levenshtein distance between two strings: the fewest characters that must be
+/// inserted, removed or replaced, or pairs of neighbouring characters that must be swapped, to
+/// turn one into the other. Swapped characters aren't edited again, so `ca` is 3 away from
+/// `abc`.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut before = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, &x) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, &y) in b.iter().enumerate() {
+            let replace = previous[j] + usize::from(x != y);
+            let mut distance = replace.min(previous[j + 1] + 1).min(current[j] + 1);
+            if i > 0 && j > 0 && x == b[j - 1] && a[i - 1] == y {
+                distance = distance.min(before[j - 1] + 1);
+            }
+            current[j + 1] = distance;
+        }
+        std::mem::swap(&mut before, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// How many typos separate `text` from `name`, ignoring case: the smallest edit distance to the
+/// whole name or to any of its words, or 0 if the name contains the text.
+pub fn name_distance(name: &str, text: &str) -> usize {
+    let name = fold(name);
+    let text = fold(text);
+    if name.contains(&text) {
+        return 0;
+    }
+    name.split_whitespace()
+        .map(|x| edit_distance(x, &text))
+        .fold(edit_distance(&name, &text), usize::min)
 }
 
+/// The names of every card and the words in them, grouped by first character and length. Typo
+/// matching only measures the distance to the groups that could be close enough, instead of to
+/// every card. Names with a typo in their first character aren't found through it.
+#[derive(Default)]
+pub struct NameIndex {
+    /// Lowercase names and words, with the id of their card.
+    buckets: HashMap<(char, usize), Vec<(String, String)>>,
+}
+
+impl NameIndex {
+    pub fn new<'a>(cards: impl IntoIterator<Item = &'a Card>) -> Self {
+        let mut buckets: HashMap<(char, usize), Vec<(String, String)>> = HashMap::new();
+        for card in cards {
+            for name in card.names() {
+                let name = fold(name);
+                let mut keys: Vec<&str> = name.split_whitespace().collect();
+                if keys.len() != 1 {
+                    keys.push(&name);
+                }
+                for key in keys {
+                    if let Some(first) = key.chars().next() {
+                        buckets
+                            .entry((first, key.chars().count()))
+                            .or_default()
+                            .push((key.to_string(), card.id.clone()));
+                    }
+                }
+            }
+        }
+        Self { buckets }
+    }
+
+    /// Ids of the cards with a name, or a word of a name, at most `max_distance` typos from
+    /// `text`, with the fewest typos of each.
+    pub fn within(&self, text: &str, max_distance: usize) -> BTreeMap<String, usize> {
+        let text = fold(text.trim());
+        let mut cards = BTreeMap::new();
+        let Some(first) = text.chars().next() else {
+            return cards;
+        };
+        let length = text.chars().count();
+        for length in length.saturating_sub(max_distance)..=length + max_distance {
+            for (name, id) in self.buckets.get(&(first, length)).into_iter().flatten() {
+                let distance = edit_distance(name, &text);
+                if distance <= max_distance {
+                    let best = cards.entry(id.clone()).or_insert(distance);
+                    *best = distance.min(*best);
+                }
+            }
+        }
+        cards
+    }
+}
+
+/// Characters in the pieces of words [`WordIndex`] finds substrings through.
+const GRAM_LENGTH: usize = 3;
+
+/// Every word of the text free text is searched in, with the cards that have it, so free text
+/// only has to be checked against the cards whose words could contain it.
+///
+/// Free text matches inside words, so besides the words, the index keeps every run of
+/// [`GRAM_LENGTH`] characters of them. A word is looked for through the cards that have all of
+/// its runs, which costs one lookup per character of the word and an intersection of the lists,
+/// instead of a pass over every word. Some of those cards may have the runs in different words
+/// and not match, which the search itself finds out. Words shorter than the runs would need a
+/// pass over every word, or an index of their own as big as the rest, so they don't narrow down
+/// the cards at all.
+#[derive(Default)]
+pub struct WordIndex {
+    /// [`fold`]ed words, with the ids of the cards that have them, sorted to find prefixes.
+    words: BTreeMap<String, HashSet<String>>,
+    /// Every run of [`GRAM_LENGTH`] characters of the words, with the ids of the cards that have
+    /// it.
+    grams: HashMap<String, HashSet<String>>,
+}
+
+impl WordIndex {
+    pub fn new<'a>(cards: impl IntoIterator<Item = &'a Card>) -> Self {
+        let mut index = Self::default();
+        for card in cards {
+            let text = card.search_text();
+            let faces = text
+                .faces
+                .iter()
+                .flat_map(|(name, description)| [name, description]);
+            let fields = [
+                &text.name,
+                &text.description,
+                &text.abilities,
+                &text.r#type,
+                &text.kins,
+                &text.keywords,
+            ]
+            .into_iter()
+            .chain(faces);
+            for field in fields {
+                for word in word_ranges(field) {
+                    let word = &field[word];
+                    for gram in grams(word) {
+                        index.grams.entry(gram).or_default().insert(card.id.clone());
+                    }
+                    index
+                        .words
+                        .entry(word.to_string())
+                        .or_default()
+                        .insert(card.id.clone());
+                }
+            }
+        }
+        index
+    }
+
+    /// Ids of the cards with some word that starts with `prefix`, going through only those words.
+    fn starting_with(&self, prefix: &str) -> HashSet<&str> {
+        self.words
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(word, _)| word.starts_with(prefix))
+            .flat_map(|(_, ids)| ids.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Ids of the cards that could have a word containing `word`, or `None` if it's too short to
+    /// look for.
+    fn inside_words(&self, word: &str) -> Option<HashSet<&str>> {
+        if word.chars().count() < GRAM_LENGTH {
+            return None;
+        }
+        let Some(mut lists) = grams(word)
+            .map(|x| self.grams.get(&x))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Some(HashSet::new());
+        };
+        lists.sort_unstable_by_key(|x| x.len());
+        let (first, rest) = lists.split_first()?;
+        Some(
+            first
+                .iter()
+                .map(String::as_str)
+                .filter(|id| rest.iter().all(|x| x.contains(*id)))
+                .collect(),
+        )
+    }
+
+    /// Ids of the cards that could contain `text`, which are the ones where every word of `text`
+    /// is inside some word, or `None` if `text` has no words long enough to look for.
+    fn containing(&self, text: &str) -> Option<HashSet<&str>> {
+        all_of(tokenize(text).iter().map(|word| self.inside_words(word)))
+    }
+}
+
+/// Every run of [`GRAM_LENGTH`] characters of `word`, none if it's shorter.
+fn grams(word: &str) -> impl Iterator<Item = String> {
+    let chars: Vec<char> = word.chars().collect();
+    let count = (chars.len() + 1).saturating_sub(GRAM_LENGTH);
+    (0..count).map(move |x| chars[x..x + GRAM_LENGTH].iter().collect())
+}
+
+/// The ids in every one of the sets, ignoring the `None`s, which could be any id. `None` if all
+/// of them are.
+fn all_of<'a>(
+    sets: impl IntoIterator<Item = Option<HashSet<&'a str>>>,
+) -> Option<HashSet<&'a str>> {
+    sets.into_iter()
+        .flatten()
+        .reduce(|a, b| a.into_iter().filter(|x| b.contains(x)).collect())
+}
+
+/// The ids in any of the sets, or `None` if any of them could be any id.
+fn any_of<'a>(
+    sets: impl IntoIterator<Item = Option<HashSet<&'a str>>>,
+) -> Option<HashSet<&'a str>> {
+    sets.into_iter().try_fold(HashSet::new(), |mut ids, set| {
+        ids.extend(set?);
+        Some(ids)
+    })
+}
+
+/// Most names suggested by [`suggestions`].
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// Names of the cards closest to `text` by [`name_distance`], closest first. Names that are more
+/// typos away than half the length of `text` aren't suggested, since they're rarely what was
+/// meant.
+pub fn suggestions<'a>(text: &str, cards: impl IntoIterator<Item = &'a Card>) -> Vec<&'a str> {
+    let max_distance = text.chars().count() / 2;
+    let mut names: Vec<(usize, &str)> = cards
+        .into_iter()
+        .flat_map(Card::names)
+        .map(|name| (name_distance(name, text), name))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect();
+    names.sort_unstable();
+    names.dedup_by(|a, b| a.1 == b.1);
+    names
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// Shortest word of the query text that's looked for on its own. Shorter ones would match almost
+/// every card.
+pub const MIN_TOKEN_LENGTH: usize = 2;
+
+/// Words too common to narrow down a search, left out of the query text when it has others.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "as", "at", "by", "for", "from", "in", "into", "is", "it", "its", "of", "on",
+    "or", "the", "that", "this", "to", "with",
+];
+
+/// Whether `word` is one of the [`STOP_WORDS`], ignoring ASCII case.
+pub fn is_stop_word(word: &str) -> bool {
+    STOP_WORDS.iter().any(|x| x.eq_ignore_ascii_case(word))
+}
+
+/// Endings [`stem`] takes off, tried in order.
+const SUFFIXES: &[&str] = &["ing", "ed", "es", "s"];
+
+/// Fewest characters [`stem`] leaves, so short words like `bring` or `shed` stay whole.
+const MIN_STEM_LENGTH: usize = 3;
+
+/// The word without a plural or verb ending, like `destroy` for `destroys`, `destroyed` and
+/// `destroying`. Stems are looked for inside words, so they still match every form of the word.
+pub fn stem(word: &str) -> &str {
+    for suffix in SUFFIXES {
+        let Some(start) = word.len().checked_sub(suffix.len()) else {
+            continue;
+        };
+        if word.is_char_boundary(start)
+            && word[start..].eq_ignore_ascii_case(suffix)
+            && word[..start].chars().count() >= MIN_STEM_LENGTH
+        {
+            return &word[..start];
+        }
+    }
+    word
+}
+
+/// The parts of the query text that must each appear in a card. Those are the [`stem`]s of its
+/// words of at least [`MIN_TOKEN_LENGTH`] characters, leaving out [stop words](is_stop_word),
+/// and its phrases in double quotes, looked for exactly as written. When that leaves nothing, it
+/// is the whole text, so a single word is looked for exactly as written.
+pub fn text_tokens(text: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut words = vec![];
+    // Every other part is inside quotes.
+    for (i, part) in text.split('"').enumerate() {
+        if i % 2 == 1 {
+            tokens.extend(Some(part.trim()).filter(|x| !x.is_empty()));
+        } else {
+            words.extend(
+                part.split_whitespace()
+                    .filter(|x| x.chars().count() >= MIN_TOKEN_LENGTH),
+            );
+        }
+    }
+    if words.iter().any(|x| !is_stop_word(x)) {
+        words.retain(|x| !is_stop_word(x));
+    }
+    tokens.extend(words.into_iter().map(stem));
+    if tokens.is_empty() {
+        vec![text.trim()]
+    } else {
+        tokens
+    }
+}
+
+/// How much a match in a name is worth, compared to one in a description.
+const NAME_WEIGHT: f32 = 3.0;
+
+/// How much a match in the type, kins or keyword names is worth, compared to one in a description.
+const OTHER_WEIGHT: f32 = 0.5;
+
+/// How much a match in each part of a card is worth to [`score_card`]. Each one is set with
+/// `WEIGHT_NAME`, `WEIGHT_DESC` and `WEIGHT_OTHER`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weights {
+    /// Names of the card and its faces.
+    pub name: f32,
+    /// Descriptions and abilities.
+    pub description: f32,
+    /// The type, kins and keyword names.
+    pub other: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Self {
+            name: NAME_WEIGHT,
+            description: 1.0,
+            other: OTHER_WEIGHT,
+        }
+    }
+}
+
+impl Weights {
+    /// The weights set in the variables `var` looks up. Weights that are missing, negative or
+    /// not numbers keep their default.
+    pub fn from_env(var: impl Fn(&str) -> Option<String>) -> Self {
+        let weight = |name: &str, default: f32| {
+            var(name)
+                .and_then(|x| x.trim().parse::<f32>().ok())
+                .filter(|x| x.is_finite() && *x >= 0.0)
+                .unwrap_or(default)
+        };
+        let default = Self::default();
+        Self {
+            name: weight("WEIGHT_NAME", default.name),
+            description: weight("WEIGHT_DESC", default.description),
+            other: weight("WEIGHT_OTHER", default.other),
+        }
+    }
+}
+
+/// How well the card matches `query`, or `None` if no face of it mentions the text. Both are
+/// compared after [`fold`]ing them, and the best match counts. Matches in each part of the card
+/// are multiplied by its [`Weights`], which by default make names worth more than descriptions
+/// and abilities, and those worth more than the type, kins and keywords. A match that is a whole
+/// word is worth twice as much as one inside a word, and matches are worth less the later in
+/// their text they are.
+pub fn score_card(card: &Card, query: &str, weights: &Weights) -> Option<f32> {
+    let query = fold(query.trim());
+    if query.is_empty() {
+        return Some(0.0);
+    }
+
+    let text = card.search_text();
+    let faces = text
+        .faces
+        .iter()
+        .flat_map(|(name, description)| [(name, weights.name), (description, weights.description)]);
+    [
+        (&text.name, weights.name),
+        (&text.description, weights.description),
+        (&text.abilities, weights.description),
+        (&text.r#type, weights.other),
+        (&text.kins, weights.other),
+        (&text.keywords, weights.other),
+    ]
+    .into_iter()
+    .chain(faces)
+    .filter_map(|(field, weight)| field_score(field, &query).map(|x| x * weight))
+    .reduce(f32::max)
+}
+
+/// The best of the matches of `query` in `field`, from 1 for a whole word at the start down
+/// towards 0.
+#[allow(clippy::cast_precision_loss)]
+fn field_score(field: &str, query: &str) -> Option<f32> {
+    field
+        .match_indices(query)
+        .map(|(start, _)| {
+            let end = start + query.len();
+            let whole_word = !field[..start]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric)
+                && !field[end..]
+                    .chars()
+                    .next()
+                    .is_some_and(char::is_alphanumeric);
+            let position = field[..start].chars().count() as f32;
+            (if whole_word { 1.0 } else { 0.5 }) / (1.0 + position / 10.0)
+        })
+        .reduce(f32::max)
+}
+
+/// Whether some face of the card mentions `query` anywhere. The same as [`score_card`] finding a
+/// match, for callers that don't need the score.
 pub fn fuzzy(card: &Card, query: &str) -> bool {
-    card.description
-        .to_lowercase()
-        .contains(&query.to_lowercase())
-        || card.name.to_lowercase().contains(&query.to_lowercase())
-        || card.r#type.to_lowercase().contains(&query.to_lowercase())
-        || card.kins.iter().any(|x| x.contains(&query.to_lowercase()))
-        || card
-            .keywords
-            .iter()
-            .any(|x| x.name.contains(&query.to_lowercase()))
+    score_card(card, query, &Weights::default()).is_some()
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum NumberProperty {
+    Cost,
+    Health,
+    Power,
+    Defense,
+}
+
+impl NumberProperty {
+    pub fn get(self, card: &Card) -> Stat {
+        match self {
+            NumberProperty::Cost => card.get_cost(),
+            NumberProperty::Health => card.get_health(),
+            NumberProperty::Power => card.get_power(),
+            NumberProperty::Defense => card.get_defense(),
+        }
+    }
+
+    pub fn get_face(self, face: &CardFace) -> Stat {
+        match self {
+            NumberProperty::Cost => face.cost,
+            NumberProperty::Health => face.health,
+            NumberProperty::Power => face.power,
+            NumberProperty::Defense => face.defense,
+        }
+    }
+}
+
+impl Display for NumberProperty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumberProperty::Cost => write!(f, "cost"),
+            NumberProperty::Health => write!(f, "health"),
+            NumberProperty::Power => write!(f, "power"),
+            NumberProperty::Defense => write!(f, "defense"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum TextProperty {
+    Name,
+    Type,
+    Set,
+}
+
+impl TextProperty {
+    /// Every value of the property across the faces of the card.
+    pub fn get(self, card: &Card) -> Vec<&str> {
+        match self {
+            TextProperty::Name => card.names().collect(),
+            TextProperty::Type => vec![card.get_type()],
+            TextProperty::Set => vec![card.set.as_str()],
+        }
+    }
+}
+
+impl Display for TextProperty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextProperty::Name => write!(f, "name"),
+            TextProperty::Type => write!(f, "type"),
+            TextProperty::Set => write!(f, "set"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ListProperty {
+    Kins,
+    Artists,
+    /// The free-form tags of the card.
+    Other,
+}
+
+impl ListProperty {
+    pub fn get(self, card: &Card) -> &[String] {
+        match self {
+            ListProperty::Kins => card.get_kins(),
+            ListProperty::Artists => &card.artists,
+            ListProperty::Other => &card.other,
+        }
+    }
+}
+
+impl Display for ListProperty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListProperty::Kins => write!(f, "kins"),
+            ListProperty::Artists => write!(f, "artists"),
+            ListProperty::Other => write!(f, "other tags"),
+        }
+    }
+}
+
+/// How a name must relate to some text, ignoring case.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum NamePattern {
+    Contains(String),
+    Prefix(String),
+    Suffix(String),
+    Exact(String),
+}
+
+impl NamePattern {
+    /// Reads a pattern where a leading or trailing `*` stands for any text. Without any `*`, the
+    /// name only has to contain the text.
+    pub fn from_wildcards(pattern: &str) -> Self {
+        let pattern = fold(pattern.trim());
+        match (pattern.strip_prefix('*'), pattern.strip_suffix('*')) {
+            (Some(rest), None) => NamePattern::Suffix(rest.to_string()),
+            (None, Some(rest)) => NamePattern::Prefix(rest.to_string()),
+            (Some(_), Some(_)) => NamePattern::Contains(pattern.trim_matches('*').to_string()),
+            (None, None) => NamePattern::Contains(pattern),
+        }
+    }
+
+    /// Reads the `name` parameter as `mode` says. Only `contains` reads wildcards.
+    pub fn with_mode(pattern: &str, mode: NameMatch) -> Self {
+        match mode {
+            NameMatch::Contains => Self::from_wildcards(pattern),
+            NameMatch::Prefix => NamePattern::Prefix(fold(pattern.trim())),
+            NameMatch::Exact => NamePattern::Exact(fold(pattern.trim())),
+        }
+    }
+
+    pub fn matches(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    /// The byte range of `name` that matches the pattern, if it matches.
+    pub fn find(&self, name: &str) -> Option<Range<usize>> {
+        let (NamePattern::Contains(x)
+        | NamePattern::Prefix(x)
+        | NamePattern::Suffix(x)
+        | NamePattern::Exact(x)) = self;
+        if x.is_empty() {
+            return Some(0..0);
+        }
+        match self {
+            NamePattern::Contains(x) => find_ignoring_case(name, x).into_iter().next(),
+            NamePattern::Prefix(x) => match_at(name, 0, x).map(|end| 0..end),
+            NamePattern::Exact(x) => match_at(name, 0, x)
+                .filter(|&end| end == name.len())
+                .map(|end| 0..end),
+            NamePattern::Suffix(x) => name
+                .char_indices()
+                .map(|(i, _)| i)
+                .find(|&i| match_at(name, i, x) == Some(name.len()))
+                .map(|start| start..name.len()),
+        }
+    }
 }
 
+impl Display for NamePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamePattern::Contains(x) => write!(f, "contains \"{x}\""),
+            NamePattern::Prefix(x) => write!(f, "starts with \"{x}\""),
+            NamePattern::Suffix(x) => write!(f, "ends with \"{x}\""),
+            NamePattern::Exact(x) => write!(f, "is \"{x}\""),
+        }
+    }
+}
+
+/// One condition of a query. Serialized for `/api/search/explain` as an object with the name of
+/// the restriction as its only key, like `{ "comparison": ["cost", { "GreaterThan": 2 }] }`.
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum QueryRestriction {
+    /// The card mentions every one of the [`text_tokens`] of the text.
     Fuzzy(String),
-    Comparison(Box<dyn Fn(&Card) -> usize>, Comparison),
-    Contains(Box<dyn Fn(&Card) -> &str>, String),
-    Has(Box<dyn Fn(&Card) -> &[String]>, String),
-    HasKw(Box<dyn Fn(&Card) -> &[Keyword]>, String),
+    /// The card mentions any of the [`text_tokens`] of the text.
+    FuzzyAny(String),
+    /// Like `Fuzzy`, but also matches names that are at most this many typos away. Once resolved
+    /// with a [`NameIndex`], the cards within that distance, with their distance, are kept so
+    /// they aren't measured again for every card.
+    Typo(
+        String,
+        usize,
+        #[serde(skip)] Option<BTreeMap<String, usize>>,
+    ),
+    Comparison(NumberProperty, Comparison),
+    Contains(TextProperty, String),
+    /// The property is the value, ignoring case.
+    Is(TextProperty, String),
+    Has(ListProperty, String),
+    /// Has a value that contains the text, ignoring case.
+    HasPart(ListProperty, String),
+    /// Has any of the values, like a kin or any kin that descends from it. The first string is
+    /// the value asked for.
+    HasAny(ListProperty, String, Vec<String>),
+    /// The name of some face matches the pattern.
+    Name(NamePattern),
+    /// The name or description of some face matches the regular expression.
+    Regex(#[serde(serialize_with = "serialize_regex")] Regex),
+    HasKw(String),
+    /// Has a keyword whose number satisfies the comparison, like `keyword:armor>=2`.
+    KeywordValue(String, Comparison),
+    /// Has a rarity, ignoring case.
+    Rarity(String),
+    /// Playable in a format.
+    LegalIn(String),
+    /// Released on or after a `YYYY-MM-DD` date.
+    ReleasedAfter(String),
+    /// Released on or before a `YYYY-MM-DD` date.
+    ReleasedBefore(String),
+    /// Doesn't match the restriction on any face.
+    Not(Box<QueryRestriction>),
+    /// Matches at least one of the restrictions.
+    AnyOf(Vec<QueryRestriction>),
+    /// Is of the kin or mentions it, as [`synergy`] says.
+    Synergy(String),
+    /// Some name or description has words starting with all or any of these folded words.
+    Words(Vec<String>, WordMatch),
+}
+
+impl QueryRestriction {
+    /// Ids of the only cards that could match, found through `index`, or `None` if the
+    /// restriction isn't about free text and any card could. Cards in it may still not match.
+    pub fn candidates<'a>(&'a self, index: &'a WordIndex) -> Option<HashSet<&'a str>> {
+        match self {
+            QueryRestriction::Fuzzy(text) => {
+                all_of(text_tokens(text).into_iter().map(|x| index.containing(x)))
+            }
+            QueryRestriction::FuzzyAny(text) => {
+                any_of(text_tokens(text).into_iter().map(|x| index.containing(x)))
+            }
+            QueryRestriction::Typo(text, _, distances) => {
+                let mut ids = all_of(text_tokens(text).into_iter().map(|x| index.containing(x)))?;
+                ids.extend(distances.as_ref()?.keys().map(String::as_str));
+                Some(ids)
+            }
+            QueryRestriction::Words(words, word_match) => {
+                let sets = words.iter().map(|word| Some(index.starting_with(word)));
+                match word_match {
+                    WordMatch::All => all_of(sets),
+                    WordMatch::Any => any_of(sets),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Checks the restriction against every face of the card, matching if any face does.
+    pub fn matches(&self, card: &Card) -> bool {
+        match self {
+            QueryRestriction::Fuzzy(query) => {
+                text_tokens(query).into_iter().all(|x| fuzzy(card, x))
+            }
+            QueryRestriction::FuzzyAny(query) => {
+                text_tokens(query).into_iter().any(|x| fuzzy(card, x))
+            }
+            QueryRestriction::Typo(query, max_distance, distances) => {
+                QueryRestriction::Fuzzy(query.clone()).matches(card)
+                    || match distances {
+                        Some(distances) => distances.contains_key(&card.id),
+                        None => card
+                            .names()
+                            .any(|x| name_distance(x, query) <= *max_distance),
+                    }
+            }
+            QueryRestriction::Comparison(property, comparison) => {
+                comparison.compare(property.get(card))
+                    || card
+                        .faces
+                        .iter()
+                        .any(|x| comparison.compare(property.get_face(x)))
+            }
+            QueryRestriction::Contains(property, value) => property
+                .get(card)
+                .iter()
+                .any(|x| fold(x).contains(&fold(value))),
+            QueryRestriction::Is(property, value) => {
+                let value = fold(value);
+                property.get(card).iter().any(|x| fold(x) == value)
+            }
+            // Tags are free-form text, so they're compared like text, the same as `HasPart`.
+            QueryRestriction::Has(ListProperty::Other, value) => {
+                let value = fold(value);
+                card.other.iter().any(|x| fold(x) == value)
+            }
+            QueryRestriction::Has(property, value) => property
+                .get(card)
+                .iter()
+                .any(|x| x.to_lowercase() == value.to_lowercase()),
+            QueryRestriction::HasPart(property, value) => {
+                let value = fold(value);
+                property.get(card).iter().any(|x| fold(x).contains(&value))
+            }
+            QueryRestriction::HasAny(property, _, values) => property
+                .get(card)
+                .iter()
+                .any(|x| values.contains(&x.to_lowercase())),
+            QueryRestriction::Name(pattern) => card.names().any(|x| pattern.matches(x)),
+            QueryRestriction::Regex(regex) => {
+                regex.is_match(&card.name)
+                    || regex.is_match(&card.description)
+                    || card
+                        .faces
+                        .iter()
+                        .any(|x| regex.is_match(&x.name) || regex.is_match(&x.description))
+            }
+            QueryRestriction::HasKw(value) => card
+                .get_keywords()
+                .iter()
+                .chain(card.faces.iter().flat_map(|x| x.keywords.iter()))
+                .any(|x| x.name.to_lowercase() == value.to_lowercase()),
+            QueryRestriction::KeywordValue(name, comparison) => card
+                .get_keywords()
+                .iter()
+                .chain(card.faces.iter().flat_map(|x| x.keywords.iter()))
+                .filter(|x| x.name.to_lowercase() == name.to_lowercase())
+                .filter_map(Keyword::value)
+                .any(|x| comparison.compare_number(x)),
+            QueryRestriction::Rarity(rarity) => card
+                .get_rarity()
+                .is_some_and(|x| x.eq_ignore_ascii_case(rarity)),
+            QueryRestriction::LegalIn(format) => card.legality.iter().any(|(x, legality)| {
+                x.eq_ignore_ascii_case(format) && legality.status.is_playable()
+            }),
+            QueryRestriction::ReleasedAfter(date) => {
+                card.release_date().is_some_and(|x| x >= date.as_str())
+            }
+            QueryRestriction::ReleasedBefore(date) => {
+                card.release_date().is_some_and(|x| x <= date.as_str())
+            }
+            QueryRestriction::Not(restriction) => !restriction.matches(card),
+            QueryRestriction::AnyOf(restrictions) => restrictions.iter().any(|x| x.matches(card)),
+            QueryRestriction::Synergy(kin) => synergy(card, kin).is_some(),
+            QueryRestriction::Words(words, word_match) => {
+                let faces = card.faces.iter().flat_map(|x| [&x.name, &x.description]);
+                let tokens: Vec<String> = [&card.name, &card.description]
+                    .into_iter()
+                    .chain(faces)
+                    .flat_map(|x| tokenize(x))
+                    .collect();
+                let has = |word: &String| tokens.iter().any(|x| x.starts_with(word.as_str()));
+                match word_match {
+                    WordMatch::All => words.iter().all(has),
+                    WordMatch::Any => words.iter().any(has),
+                }
+            }
+        }
+    }
+}
+
+impl QueryRestriction {
+    /// Byte ranges of `text` that satisfy the restriction, where `text` is a name or, when
+    /// `is_name` is false, a description. Restrictions on anything else find nothing.
+    fn find_in(&self, text: &str, is_name: bool) -> Vec<Range<usize>> {
+        match self {
+            QueryRestriction::Fuzzy(query)
+            | QueryRestriction::FuzzyAny(query)
+            | QueryRestriction::Typo(query, ..) => text_tokens(query)
+                .into_iter()
+                .flat_map(|x| find_ignoring_case(text, &fold(x)))
+                .collect(),
+            QueryRestriction::Name(pattern) if is_name => pattern.find(text).into_iter().collect(),
+            QueryRestriction::Regex(regex) => regex.find_iter(text).map(|x| x.range()).collect(),
+            QueryRestriction::Words(words, _) => word_ranges(text)
+                .into_iter()
+                .filter(|x| {
+                    let word = fold(&text[x.clone()]);
+                    words.iter().any(|x| word.starts_with(x.as_str()))
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+impl Display for QueryRestriction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QueryRestriction::Fuzzy(query) => match text_tokens(query).as_slice() {
+                [token] => write!(f, "the text mentions \"{token}\""),
+                tokens => write!(f, "the text mentions \"{}\"", tokens.join("\" and \"")),
+            },
+            QueryRestriction::FuzzyAny(query) => {
+                write!(
+                    f,
+                    "the text mentions \"{}\"",
+                    text_tokens(query).join("\" or \"")
+                )
+            }
+            QueryRestriction::Typo(query, max_distance, _) => write!(
+                f,
+                "the text mentions \"{query}\" or the name is within {max_distance} typos of it"
+            ),
+            QueryRestriction::Comparison(property, comparison) => {
+                write!(f, "{property} is {comparison}")
+            }
+            QueryRestriction::Contains(property, value) => {
+                write!(f, "{property} contains \"{value}\"")
+            }
+            QueryRestriction::Has(property, value) => write!(f, "{property} include \"{value}\""),
+            QueryRestriction::HasPart(property, value) => {
+                write!(f, "{property} include one containing \"{value}\"")
+            }
+            QueryRestriction::Is(property, value) => write!(f, "{property} is \"{value}\""),
+            QueryRestriction::HasAny(property, value, _) => {
+                write!(f, "{property} include \"{value}\" or one within it")
+            }
+            QueryRestriction::Name(pattern) => write!(f, "name {pattern}"),
+            QueryRestriction::Regex(regex) => write!(f, "name or description match /{regex}/"),
+            QueryRestriction::HasKw(value) => write!(f, "keywords include \"{value}\""),
+            QueryRestriction::KeywordValue(name, comparison) => {
+                write!(f, "keyword \"{name}\" is {comparison}")
+            }
+            QueryRestriction::Rarity(rarity) => write!(f, "rarity is {rarity}"),
+            QueryRestriction::LegalIn(format) => write!(f, "legal in {format}"),
+            QueryRestriction::ReleasedAfter(date) => write!(f, "released on or after {date}"),
+            QueryRestriction::ReleasedBefore(date) => write!(f, "released on or before {date}"),
+            QueryRestriction::Not(restriction) => write!(f, "not {restriction}"),
+            QueryRestriction::Synergy(kin) => write!(f, "is or mentions the {kin} kin"),
+            QueryRestriction::AnyOf(restrictions) => {
+                let restrictions: Vec<String> =
+                    restrictions.iter().map(ToString::to_string).collect();
+                write!(f, "({})", restrictions.join(" or "))
+            }
+            QueryRestriction::Words(words, word_match) => {
+                let words: Vec<String> = words.iter().map(|x| format!("\"{x}\"")).collect();
+                match word_match {
+                    WordMatch::All => {
+                        write!(f, "the text has words starting with {}", words.join(", "))
+                    }
+                    WordMatch::Any => write!(
+                        f,
+                        "the text has a word starting with {}",
+                        words.join(" or ")
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Part of a text where a query matched, in bytes.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct MatchRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Where a query matched a card, by field, like `name` or `faces[0].description`.
+pub type Highlights = BTreeMap<String, Vec<MatchRange>>;
+
+fn serialize_regex<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(regex.as_str())
+}
+
+/// A parsed query. A card matches it when it satisfies every restriction.
+#[derive(Serialize)]
+pub struct Query {
+    pub restrictions: Vec<QueryRestriction>,
+}
+
+impl Query {
+    pub fn matches(&self, card: &Card) -> bool {
+        self.restrictions.iter().all(|x| x.matches(card))
+    }
+
+    /// Ids of the only cards that could match, found through `index`, or `None` if any card could.
+    pub fn candidates<'a>(&'a self, index: &'a WordIndex) -> Option<HashSet<&'a str>> {
+        all_of(self.restrictions.iter().map(|x| x.candidates(index)))
+    }
+
+    /// Makes kin restrictions also match every kin that descends from the one asked for.
+    pub fn expand_kins(&mut self, hierarchy: &KinHierarchy) {
+        fn expand(restriction: &mut QueryRestriction, hierarchy: &KinHierarchy) {
+            match restriction {
+                QueryRestriction::Not(restriction) => expand(restriction, hierarchy),
+                QueryRestriction::AnyOf(restrictions) => {
+                    for restriction in restrictions {
+                        expand(restriction, hierarchy);
+                    }
+                }
+                QueryRestriction::Has(ListProperty::Kins, kin) => {
+                    let kins = hierarchy.with_descendants(kin);
+                    *restriction =
+                        QueryRestriction::HasAny(ListProperty::Kins, std::mem::take(kin), kins);
+                }
+                _ => (),
+            }
+        }
+
+        for restriction in &mut self.restrictions {
+            expand(restriction, hierarchy);
+        }
+    }
+
+    /// Lets the free text of the query also match names with typos, at most `max_distance` or
+    /// the [`typo_limit`] of the text.
+    pub fn allow_typos(&mut self, max_distance: Option<usize>) {
+        for restriction in &mut self.restrictions {
+            match restriction {
+                QueryRestriction::Fuzzy(text) if !text.is_empty() => {
+                    let max_distance = max_distance.unwrap_or_else(|| typo_limit(text));
+                    *restriction = QueryRestriction::Typo(std::mem::take(text), max_distance, None);
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Finds the cards within reach of every typo-tolerant restriction through `index`.
+    pub fn resolve_typos(&mut self, index: &NameIndex) {
+        for restriction in &mut self.restrictions {
+            if let QueryRestriction::Typo(text, max_distance, distances) = restriction {
+                *distances = Some(index.within(text, *max_distance));
+            }
+        }
+    }
+
+    /// The [`relevance`] of the card for the free text of the query.
+    pub fn relevance(&self, card: &Card) -> u8 {
+        self.restrictions
+            .iter()
+            .filter_map(|x| match x {
+                QueryRestriction::Fuzzy(text)
+                | QueryRestriction::FuzzyAny(text)
+                | QueryRestriction::Typo(text, ..) => Some(relevance(card, text)),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// The [`score_card`] of the card for every word of the free text, added up. Higher is a
+    /// better match.
+    pub fn score(&self, card: &Card, weights: &Weights) -> f32 {
+        self.restrictions
+            .iter()
+            .filter_map(|x| match x {
+                QueryRestriction::Fuzzy(text)
+                | QueryRestriction::FuzzyAny(text)
+                | QueryRestriction::Typo(text, ..) => Some(text),
+                _ => None,
+            })
+            .flat_map(|x| text_tokens(x))
+            .filter_map(|x| score_card(card, x, weights))
+            .sum()
+    }
+
+    /// The free text of the query, or nothing if it only has other kinds of restrictions.
+    pub fn free_text(&self) -> Option<String> {
+        let text: Vec<&str> = self
+            .restrictions
+            .iter()
+            .filter_map(|x| match x {
+                QueryRestriction::Fuzzy(text)
+                | QueryRestriction::FuzzyAny(text)
+                | QueryRestriction::Typo(text, ..) => Some(text.as_str()),
+                _ => None,
+            })
+            .filter(|x| !x.trim().is_empty())
+            .collect();
+        (!text.is_empty()).then(|| text.join(" "))
+    }
+
+    /// How many typos away from the card's names the typo-tolerant parts of the query are, added
+    /// up. Lower is a better match.
+    pub fn typo_score(&self, card: &Card) -> usize {
+        self.restrictions
+            .iter()
+            .filter_map(|x| match x {
+                QueryRestriction::Typo(query, _, distances) => distances
+                    .as_ref()
+                    .and_then(|x| x.get(&card.id).copied())
+                    .or_else(|| card.names().map(|x| name_distance(x, query)).min()),
+                _ => None,
+            })
+            .sum()
+    }
+
+    /// Where the text restrictions of the query match the names and descriptions of the card.
+    /// Overlapping matches are merged, and fields without any match are left out.
+    pub fn highlights(&self, card: &Card) -> Highlights {
+        let mut fields = vec![
+            ("name".to_string(), card.name.as_str(), true),
+            ("description".to_string(), card.description.as_str(), false),
+        ];
+        for (i, face) in card.faces.iter().enumerate() {
+            fields.push((format!("faces[{i}].name"), face.name.as_str(), true));
+            fields.push((
+                format!("faces[{i}].description"),
+                face.description.as_str(),
+                false,
+            ));
+        }
+
+        let mut highlights = Highlights::new();
+        for (field, text, is_name) in fields {
+            let mut ranges: Vec<Range<usize>> = self
+                .restrictions
+                .iter()
+                .flat_map(|x| x.find_in(text, is_name))
+                .filter(|x| !x.is_empty())
+                .collect();
+            ranges.sort_by_key(|x| x.start);
+
+            let mut merged: Vec<MatchRange> = vec![];
+            for range in ranges {
+                match merged.last_mut() {
+                    Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                    _ => merged.push(MatchRange {
+                        start: range.start,
+                        end: range.end,
+                    }),
+                }
+            }
+            if !merged.is_empty() {
+                highlights.insert(field, merged);
+            }
+        }
+        highlights
+    }
+}
+
+impl Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let restrictions: Vec<String> = self
+            .restrictions
+            .iter()
+            .filter(|x| !matches!(x, QueryRestriction::Fuzzy(query) if query.is_empty()))
+            .map(ToString::to_string)
+            .collect();
+
+        if restrictions.is_empty() {
+            write!(f, "all cards")
+        } else {
+            write!(f, "cards where {}", restrictions.join(" and "))
+        }
+    }
+}
+
+/// Fewest cards checked on one thread. Catalogs smaller than this are searched on just one, since
+/// splitting them costs more than it saves.
+pub const SEARCH_CHUNK: usize = 512;
+
+/// The cards `matches` accepts, in the order they come in. They're checked in chunks of
+/// [`SEARCH_CHUNK`] spread over every core.
+fn filter_cards<'a>(
+    cards: impl IntoIterator<Item = &'a Card>,
+    matches: impl Fn(&Card) -> bool + Sync,
+) -> Vec<&'a Card> {
+    let cards: Vec<&Card> = cards.into_iter().collect();
+    if cards.len() <= SEARCH_CHUNK {
+        return cards.into_iter().filter(|x| matches(x)).collect();
+    }
+    cards
+        .par_chunks(SEARCH_CHUNK)
+        .flat_map_iter(|chunk| chunk.iter().copied().filter(|x| matches(x)))
+        .collect()
+}
+
+pub fn search<'a>(query: &Query, cards: impl IntoIterator<Item = &'a Card>) -> Vec<&'a Card> {
+    filter_cards(cards, |x| query.matches(x))
+}
+
+/// Like [`search`], but cards also match if their translation to one of `languages` does.
+pub fn search_localized<'a>(
+    query: &Query,
+    cards: impl IntoIterator<Item = &'a Card>,
+    languages: &[String],
+) -> Vec<&'a Card> {
+    filter_cards(cards, |x| {
+        query.matches(x)
+            || (x.localization(languages).is_some() && query.matches(&x.localized(languages)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::web;
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_cards::numbered;
+
+    fn params(query: &str) -> QueryParams {
+        let mut params = web::Query::<QueryParams>::from_query(query)
+            .unwrap()
+            .into_inner();
+        params.read_repeated(&web::Query::<Vec<(String, String)>>::from_query(query).unwrap());
+        params.normalize();
+        params
+    }
+
+    #[test]
+    fn every_parameter_is_read() {
+        let params = params("query=card&cost=%3E%3D3&lang=es&sort=collector_number");
+        assert_eq!(params.query.as_deref(), Some("card"));
+        assert_eq!(params.cost.as_deref(), Some(">=3"));
+        assert_eq!(params.lang.as_deref(), Some("es"));
+        assert!(matches!(params.sort, Some(SortOrder::CollectorNumber)));
+    }
+
+    #[test]
+    fn missing_parameters_match_everything() {
+        let params = params("");
+        assert!(params.query.is_none() && params.cost.is_none());
+        let query = params.parse().unwrap();
+        assert!(query.matches(&numbered(0)));
+        assert!(query.matches(&numbered(7)));
+    }
+
+    #[test]
+    fn cost_is_combined_with_the_query() {
+        let query = params("query=card&cost=%3E%3D3").parse().unwrap();
+        assert!(!query.matches(&numbered(2)));
+        assert!(query.matches(&numbered(3)));
+
+        let query = params("query=nothing&cost=3").parse().unwrap();
+        assert!(!query.matches(&numbered(3)));
+    }
+
+    #[test]
+    fn highlights_point_into_the_text() {
+        let query = params("query=card&name=*7").parse().unwrap();
+        let highlights = query.highlights(&numbered(7));
+        assert_eq!(
+            highlights["name"],
+            vec![
+                MatchRange { start: 0, end: 4 },
+                MatchRange { start: 5, end: 6 },
+            ]
+        );
+        assert_eq!(
+            highlights["description"],
+            vec![MatchRange { start: 7, end: 11 }]
+        );
+    }
+
+    #[test]
+    fn edit_distance_counts_typos() {
+        assert_eq!(edit_distance("siphon", "siphon"), 0);
+        assert_eq!(edit_distance("sifon", "siphon"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("carapce", "carapace"), 1);
+        assert_eq!(edit_distance("crad", "card"), 1);
+        assert_eq!(edit_distance("ca", "abc"), 3);
+        assert_eq!(name_distance("Blood Siphon", "Sifon"), 2);
+        assert_eq!(name_distance("Blood Siphon", "blood"), 0);
+    }
+
+    #[test]
+    fn suggestions_are_the_closest_names() {
+        let cards: Vec<Card> = [1, 2, 3, 4, 5].map(numbered).into();
+        assert_eq!(
+            suggestions("crad 3", &cards),
+            vec!["Card 3", "Card 1", "Card 2"]
+        );
+        assert!(suggestions("siphon", &cards).is_empty());
+
+        let query = params("query=crad&cost=3").parse().unwrap();
+        assert_eq!(query.free_text().as_deref(), Some("crad"));
+        assert!(params("cost=3").parse().unwrap().free_text().is_none());
+    }
+
+    #[test]
+    fn typo_limit_grows_with_the_text() {
+        assert_eq!(typo_limit("cxrd"), 1);
+        assert_eq!(typo_limit("carapce"), 1);
+        assert_eq!(typo_limit("blood sifon"), 2);
+        assert_eq!(typo_limit("a very long card name"), DEFAULT_MAX_DISTANCE);
+    }
+
+    #[test]
+    fn name_index_finds_close_names() {
+        let cards: Vec<Card> = [1, 2].map(numbered).into();
+        let index = NameIndex::new(&cards);
+        let found = index.within("Crad", 1);
+        assert_eq!(found.get("card_1"), Some(&1));
+        assert_eq!(found.get("card_2"), Some(&1));
+        assert_eq!(
+            index.within("card 2", 0).keys().collect::<Vec<_>>(),
+            ["card_2"]
+        );
+        assert!(index.within("xard", 1).is_empty());
+
+        let mut query = params("query=crad&fuzzy=true").parse().unwrap();
+        query.resolve_typos(&index);
+        assert!(query.matches(&cards[0]));
+        assert_eq!(query.typo_score(&cards[0]), 1);
+    }
+
+    #[test]
+    fn filters_match_any_value_of_every_filter() {
+        let mut first = numbered(1);
+        first.artists = vec!["Ana".to_string()];
+        let mut second = numbered(2);
+        second.artists = vec!["Bo".to_string()];
+        second.set = "Öther".to_string();
+
+        let query = params("artist=ana&artist=BO&set=test").parse().unwrap();
+        assert!(query.matches(&first));
+        assert!(!query.matches(&second));
+        assert!(params("artist=ana&artist=bo")
+            .parse()
+            .unwrap()
+            .matches(&second));
+        assert!(!params("set=tes").parse().unwrap().matches(&first));
+        assert!(params("set=OTHER").parse().unwrap().matches(&second));
+        assert!(!params("artist=nobody").parse().unwrap().matches(&first));
+        assert_eq!(
+            params("artist=ana&keyword=x&keyword=y")
+                .applied_filters()
+                .into_iter()
+                .map(|(name, values)| (name, values.len()))
+                .collect::<Vec<_>>(),
+            [("artist", 1), ("keyword", 2)]
+        );
+    }
+
+    #[test]
+    fn synergy_matches_members_and_mentions() {
+        let mut member = numbered(1);
+        member.kins = vec!["Undead".to_string()];
+        let mut mention = numbered(2);
+        mention.abilities = vec!["Undeads you control get +1 power".to_string()];
+        let other = numbered(3);
+
+        assert_eq!(synergy(&member, "undead"), Some(SynergyMatch::Membership));
+        assert_eq!(synergy(&mention, "UNDEAD"), Some(SynergyMatch::Text));
+        assert_eq!(synergy(&other, "undead"), None);
+        assert_eq!(synergy(&mention, "dead"), None);
+        member.kins.push("Élan".to_string());
+        assert_eq!(synergy(&member, "elan"), Some(SynergyMatch::Membership));
+        let query = params("synergy=undead").parse().unwrap();
+        assert!(query.matches(&member) && query.matches(&mention) && !query.matches(&other));
+    }
+
+    #[test]
+    fn word_index_narrows_free_text() {
+        let mut cards: Vec<Card> = [1, 2, 3].map(numbered).into();
+        cards[0].description = "Heal a creature".to_string();
+        cards[1].description = "Healing test, deal damage".to_string();
+        let index = WordIndex::new(&cards);
+        let candidates = |text: &str| {
+            let query = params(text).parse().unwrap();
+            let mut ids: Vec<String> = query
+                .candidates(&index)
+                .map(|x| x.into_iter().map(str::to_string).collect())
+                .unwrap_or_default();
+            ids.sort_unstable();
+            ids
+        };
+
+        assert_eq!(candidates("query=heal"), ["card_1", "card_2"]);
+        assert_eq!(candidates("query=heal+damage"), ["card_2"]);
+        assert_eq!(
+            candidates("query=heal+damage&match=any"),
+            ["card_1", "card_2"]
+        );
+        assert_eq!(candidates("query=deal&text_mode=words"), ["card_2"]);
+        assert_eq!(candidates("query=test,+deal"), ["card_2"]);
+        assert_eq!(candidates("query=eali"), ["card_2"]);
+        assert!(params("query=he")
+            .parse()
+            .unwrap()
+            .candidates(&index)
+            .is_none());
+        assert!(candidates("query=cost:1").is_empty());
+        assert!(params("query=cost:1")
+            .parse()
+            .unwrap()
+            .candidates(&index)
+            .is_none());
+    }
+
+    #[test]
+    fn fuzzy_mode_tolerates_typos_in_names() {
+        assert!(!params("query=cxrd").parse().unwrap().matches(&numbered(1)));
+        let query = params("query=cxrd&fuzzy=true").parse().unwrap();
+        assert!(query.matches(&numbered(1)));
+        assert_eq!(query.typo_score(&numbered(1)), 1);
+        assert!(!params("query=cxrd&fuzzy=true&max_distance=0")
+            .parse()
+            .unwrap()
+            .matches(&numbered(1)));
+    }
+
+    #[test]
+    fn costless_cards_match_no_cost() {
+        let mut token = numbered(0);
+        token.cost = Stat::None;
+        for cost in ["0", "%3E%3D0", "%3C5", "!%3D3"] {
+            let query = params(&format!("cost={cost}")).parse().unwrap();
+            assert!(!query.matches(&token), "cost={cost}");
+        }
+
+        let token: Card = serde_json::from_value(json!({
+            "id": "token",
+            "name": "Token",
+            "description": "",
+            "health": 1,
+            "defense": 0,
+            "power": 1,
+            "type": "creature",
+            "set": "Test",
+            "legality": {},
+        }))
+        .unwrap();
+        assert_eq!(token.get_cost(), Stat::None);
+    }
+
+    #[test]
+    fn cards_without_stats_match_no_stat() {
+        let command: Card = serde_json::from_value(json!({
+            "id": "command",
+            "name": "Command",
+            "description": "",
+            "cost": 1,
+            "type": "command",
+            "set": "Test",
+            "legality": {},
+        }))
+        .unwrap();
+        assert_eq!(
+            [command.power, command.health, command.defense],
+            [Stat::None; 3]
+        );
+        for stat in ["power=0", "health=%3E%3D0", "defense=%3C5", "power=!%3D3"] {
+            let query = params(stat).parse().unwrap();
+            assert!(!query.matches(&command), "{stat}");
+        }
+        assert!(params("cost=1").parse().unwrap().matches(&command));
+    }
+
+    #[test]
+    fn relevance_prefers_names() {
+        let card = numbered(3);
+        assert_eq!(relevance(&card, "card 3"), 4);
+        assert_eq!(relevance(&card, "CARD"), 3);
+        assert_eq!(relevance(&card, "d 3"), 2);
+        assert_eq!(relevance(&card, "test"), 1);
+        assert_eq!(relevance(&card, "mantis"), 0);
+        assert_eq!(relevance(&card, ""), 0);
+        assert!(matches!(
+            params("order=relevance").sort,
+            Some(SortOrder::Relevance)
+        ));
+    }
+
+    #[test]
+    fn cost_bounds_include_equal_costs() {
+        let matching = |cost: &str| -> Vec<usize> {
+            let query = params(&format!("cost={cost}")).parse().unwrap();
+            (0..7).filter(|&x| query.matches(&numbered(x))).collect()
+        };
+        assert_eq!(matching("3"), [3]);
+        assert_eq!(matching("%3E%3D3"), [3, 4, 5, 6]);
+        assert_eq!(matching("%3C%3D2"), [0, 1, 2]);
+        assert_eq!(matching("%3E4"), [5, 6]);
+        assert_eq!(matching("%3C1"), [0]);
+        assert_eq!(matching("2-4"), [2, 3, 4]);
+        assert_eq!(matching("3-3"), [3]);
+        assert_eq!(matching("0-0"), [0]);
+    }
+
+    #[test]
+    fn comparisons_are_parsed() {
+        let parse = |text| format!("{:?}", text_comparison_parser(text).unwrap());
+        assert_eq!(parse("4"), "Equal(4)");
+        assert_eq!(parse("=4"), "Equal(4)");
+        assert_eq!(parse(">=4"), "GreaterThanOrEqual(4)");
+        assert_eq!(parse("<=2"), "LowerThanOrEqual(2)");
+        assert_eq!(parse(">4"), "GreaterThan(4)");
+        assert_eq!(parse("<2"), "LowerThan(2)");
+        assert_eq!(parse("!=3"), "NotEqual(3)");
+        assert_eq!(parse("2-4"), "Between(2, 4)");
+        assert!(text_comparison_parser("-4").is_err());
+        assert!(text_comparison_parser("four").is_err());
+    }
+
+    #[test]
+    fn stats_are_filtered_like_cost() {
+        let mut strong = numbered(1);
+        strong.power = Stat::Fixed(5);
+        strong.health = Stat::Fixed(2);
+        let query = params("power=%3E%3D4&health=%3C%3D2").parse().unwrap();
+        assert!(query.matches(&strong));
+        assert!(!query.matches(&numbered(1)));
+        assert!(params("defense=1").parse().unwrap().matches(&numbered(1)));
+        assert!(params("health=cheap").parse().is_err());
+    }
+
+    #[test]
+    fn conflicting_bounds_match_nothing() {
+        let query = params("cost=4-2").parse().unwrap();
+        assert!((0..7).all(|x| !query.matches(&numbered(x))));
+        let query = params("power=%3E%3D5&query=p%3A%3C%3D2").parse().unwrap();
+        assert!(!query.matches(&numbered(1)));
+    }
+
+    #[test]
+    fn exclusions_remove_matching_cards() {
+        let mut undead = numbered(1);
+        undead.kins = vec!["undead".to_string()];
+        let mut spell = numbered(2);
+        spell.r#type = "command".to_string();
+
+        let query = params("query=card&exclude_type=Command&exclude_kin=Undead")
+            .parse()
+            .unwrap();
+        assert!(query.matches(&numbered(3)));
+        assert!(!query.matches(&undead));
+        assert!(!query.matches(&spell));
+
+        let query = params("exclude_kin=bug&exclude_kin=undead")
+            .parse()
+            .unwrap();
+        assert!(!query.matches(&undead));
+        assert!(query.matches(&spell));
+
+        let mut query = params("exclude_kin=undead").parse().unwrap();
+        query.expand_kins(
+            &KinHierarchy::from_links(HashMap::from([("ghoul".to_string(), "undead".to_string())]))
+                .0,
+        );
+        undead.kins = vec!["ghoul".to_string()];
+        assert!(!query.matches(&undead));
+    }
+
+    #[test]
+    fn kins_can_be_all_or_any() {
+        let mut both = numbered(1);
+        both.kins = vec!["Bug".to_string(), "Undead".to_string()];
+        let mut bug = numbered(2);
+        bug.kins = vec!["bug".to_string()];
+        let mut ghoul = numbered(3);
+        ghoul.kins = vec!["ghoul".to_string()];
+        let matching = |query: &str, hierarchy: Option<&KinHierarchy>| {
+            let mut query = params(query).parse().unwrap();
+            if let Some(hierarchy) = hierarchy {
+                query.expand_kins(hierarchy);
+            }
+            [&both, &bug, &ghoul].map(|x| query.matches(x))
+        };
+
+        assert_eq!(matching("kins_all=bug,UNDEAD", None), [true, false, false]);
+        assert_eq!(matching("kins_any=undead,+bug", None), [true, true, false]);
+        assert_eq!(
+            matching("kins_any=undead,ghoul&kins_all=bug", None),
+            [true, false, false]
+        );
+        assert_eq!(
+            matching("kins_any=undead,bug&query=card+2", None),
+            [false, true, false]
+        );
+        let hierarchy =
+            KinHierarchy::from_links(HashMap::from([("ghoul".to_string(), "undead".to_string())]))
+                .0;
+        assert_eq!(
+            matching("kins_any=undead,spider", Some(&hierarchy)),
+            [true, false, true]
+        );
+    }
+
+    #[test]
+    fn text_is_split_in_words() {
+        assert_eq!(
+            tokenize("Deal 2 damage, then heal."),
+            ["deal", "2", "damage", "then", "heal"]
+        );
+        assert_eq!(tokenize("  Blood-Siphon's  "), ["blood", "siphon", "s"]);
+        assert!(tokenize("... !").is_empty());
+    }
+
+    #[test]
+    fn word_mode_matches_word_starts() {
+        let mut heart = numbered(1);
+        heart.description = "Heals the heart of an art lover".to_string();
+        let mut hearty = numbered(2);
+        hearty.description = "A hearty meal".to_string();
+
+        assert!(params("query=art").parse().unwrap().matches(&hearty));
+        let query = params("query=art&text_mode=words").parse().unwrap();
+        assert!(query.matches(&heart));
+        assert!(!query.matches(&hearty));
+        assert!(params("query=hear&text_mode=words")
+            .parse()
+            .unwrap()
+            .matches(&hearty));
+
+        let all = params("query=art+meal&text_mode=words").parse().unwrap();
+        let any = params("query=art+meal&text_mode=words&word_match=any")
+            .parse()
+            .unwrap();
+        assert!(!all.matches(&heart) && !all.matches(&hearty));
+        assert!(any.matches(&heart) && any.matches(&hearty));
+        assert_eq!(
+            query.highlights(&heart)["description"],
+            vec![MatchRange { start: 22, end: 25 }]
+        );
+    }
+
+    #[test]
+    fn cards_can_be_found_by_their_other_tags() {
+        let mut card = numbered(1);
+        card.other = vec![
+            "Promo".to_string(),
+            "Kickstarter exclusive".to_string(),
+            "Café".to_string(),
+        ];
+        let matches = |query: &str| params(query).parse().unwrap().matches(&card);
+
+        assert!(matches("other=promo"));
+        assert!(matches("other=KICKSTARTER+EXCLUSIVE"));
+        assert!(!matches("other=kickstarter"));
+        assert!(matches("other=kickstarter&other_substring=true"));
+        assert!(!matches("other=token&other_substring=true"));
+        assert!(matches("other=cafe"));
+        assert!(matches("other=CAF&other_substring=true"));
+        assert!(!params("other=promo").parse().unwrap().matches(&numbered(2)));
+    }
+
+    #[test]
+    fn names_match_as_the_mode_says() {
+        let mut card = numbered(1);
+        card.name = "Éclair of Størm".to_string();
+        let matches = |query: &str| params(query).parse().unwrap().matches(&card);
+
+        assert!(matches("name=OF+ST%C3%98RM"));
+        assert!(matches("name=of&match_mode=contains"));
+        assert!(matches("name=ECLAIR&match_mode=prefix"));
+        assert!(!matches("name=of&match_mode=prefix"));
+        assert!(matches("name=%C3%89CLAIR+OF+St%C3%B8rm&match_mode=exact"));
+        assert!(!matches("name=eclair&match_mode=exact"));
+        // Wildcards are text in the other modes.
+        assert!(!matches("name=*storm&match_mode=prefix"));
+    }
+
+    #[test]
+    fn accents_and_case_are_ignored() {
+        let mut card = numbered(1);
+        card.name = "Härkönnen".to_string();
+        card.description = "Dévore un Saint".to_string();
+        assert!(params("query=harkonnen").parse().unwrap().matches(&card));
+        assert!(params("query=HÄRKÖNNEN").parse().unwrap().matches(&card));
+        assert!(params("query=devore+un+saint")
+            .parse()
+            .unwrap()
+            .matches(&card));
+        assert!(params("name=hark*").parse().unwrap().matches(&card));
+
+        card.description = "Ha\u{308}rko\u{308}nnen".to_string();
+        let highlights = params("query=harkonnen").parse().unwrap().highlights(&card);
+        assert_eq!(
+            highlights["description"],
+            vec![MatchRange { start: 0, end: 13 }]
+        );
+        assert_eq!(highlights["name"], vec![MatchRange { start: 0, end: 11 }]);
+    }
+
+    #[test]
+    fn every_word_of_the_text_is_needed() {
+        let mut ritual = numbered(1);
+        ritual.name = "Ritual of Blood".to_string();
+
+        assert!(params("query=blood+ritual")
+            .parse()
+            .unwrap()
+            .matches(&ritual));
+        assert!(!params("query=blood+dance")
+            .parse()
+            .unwrap()
+            .matches(&ritual));
+        assert!(params("query=blood+dance&match=any")
+            .parse()
+            .unwrap()
+            .matches(&ritual));
+        assert!(params("query=blood+x").parse().unwrap().matches(&ritual));
+        assert!(!params("query=x").parse().unwrap().matches(&ritual));
+        assert_eq!(text_tokens("blood x ritual"), ["blood", "ritual"]);
+        assert_eq!(text_tokens(" x "), ["x"]);
+    }
+
+    #[test]
+    fn queries_are_normalized() {
+        let matches = |card: &Card, text: &str| {
+            let mut query = params("");
+            query.query = Some(text.to_string());
+            query.normalize();
+            query.parse().unwrap().matches(card)
+        };
+        let mut card = numbered(1);
+        card.name = "Mäkelä's Ward".to_string();
+        card.kins = vec!["Mäkelä".to_string()];
+        let decomposed = "Ma\u{308}kela\u{308}";
+
+        assert!(matches(&card, &format!("{decomposed}\u{2019}s   ward")));
+        assert!(matches(
+            &card,
+            &format!("\u{201C}{decomposed}'s ward\u{201D}")
+        ));
+        assert!(matches(&card, &format!("k:{decomposed}")));
+        assert!(!matches(&card, "k:makela"));
+
+        card.name = "Mäkelä\u{2019}s Ward".to_string();
+        card.build_search_cache();
+        assert!(matches(&card, "mäkelä's ward"));
+        assert_eq!(
+            normalize_text("  a\u{2014}b \t \u{201C}c\u{201D}  "),
+            "a-b \"c\""
+        );
+
+        let mut query = params("");
+        query.regex = Some(format!("{decomposed}  \u{2013}\u{201C}"));
+        query.normalize();
+        assert_eq!(query.regex.as_deref(), Some("Mäkelä  \u{2013}\u{201C}"));
+    }
+
+    #[test]
+    fn query_words_are_stemmed() {
+        let mut card = numbered(1);
+        card.description = "Destroy a creature. The destroyed creature's owner draws.".to_string();
+        for query in ["destroys+a+creature", "destroying+the+creatures", "draw"] {
+            assert!(params(&format!("query={query}"))
+                .parse()
+                .unwrap()
+                .matches(&card));
+        }
+        assert_eq!(
+            text_tokens("destroys the creatures"),
+            ["destroy", "creatur"]
+        );
+        assert_eq!(text_tokens("bring the"), ["bring"]);
+        assert_eq!(text_tokens("the"), ["the"]);
+        assert!(params("query=destroys&text_mode=words")
+            .parse()
+            .unwrap()
+            .matches(&card));
+
+        // Quoted phrases are matched as written.
+        assert_eq!(text_tokens("\"destroys a\" owner"), ["destroys a", "owner"]);
+        assert!(!params("query=%22destroys%22")
+            .parse()
+            .unwrap()
+            .matches(&card));
+        let query = params("query=%22destroyed+creature%22").parse().unwrap();
+        assert!(query.matches(&card));
+        let highlights = query.highlights(&card);
+        let range = &highlights["description"][0];
+        assert_eq!(
+            &card.description[range.start..range.end],
+            "destroyed creature"
+        );
+    }
+
+    #[test]
+    fn scoped_terms_are_split_from_the_text() {
+        let mut card = numbered(3);
+        card.name = "The Great Devourer".to_string();
+        card.kins = vec!["insect".to_string()];
+        card.artists = vec!["Ann".to_string()];
+
+        let query =
+            params("query=name%3A%22great+devourer%22+k%3Ainsect+c%3A3+set%3Atest+a%3Aann+test")
+                .parse()
+                .unwrap();
+        assert!(query.matches(&card));
+        assert_eq!(query.free_text().as_deref(), Some("test"));
+        assert!(!params("query=name%3A%22devourer+great%22")
+            .parse()
+            .unwrap()
+            .matches(&card));
+        assert!(!params("query=set%3Aother").parse().unwrap().matches(&card));
+
+        let error = params("query=colour%3Ared").parse().unwrap_err();
+        assert!(matches!(&error, Errors::UnknownParam(x) if x == "colour"));
+        assert!(error.to_string().contains("artist"));
+    }
+
+    #[test]
+    fn scores_rank_where_the_text_is() {
+        let named = |name: &str, description: &str| {
+            let mut card = numbered(1);
+            card.name = name.to_string();
+            card.description = description.to_string();
+            card
+        };
+        let weights = Weights::default();
+        let score = |card: &Card| score_card(card, "blood", &weights).unwrap();
+
+        let exact = named("Blood", "");
+        let later = named("Sea of Blood", "");
+        let inside = named("Bloodless", "");
+        let described = named("Rite", "Drink blood");
+        let mut kin = named("Rite", "");
+        kin.kins = vec!["blood".to_string()];
+
+        assert!(score(&exact) > score(&later));
+        assert!(score(&later) > score(&inside));
+        assert!(score(&inside) > score(&described));
+        assert!(score(&described) > score(&kin));
+        assert!(score_card(&named("Rite", ""), "blood", &weights).is_none());
+        assert!(fuzzy(&exact, "BLOOD") && !fuzzy(&exact, "bone"));
+
+        let query = params("query=sea+blood").parse().unwrap();
+        assert!(query.score(&later, &weights) > query.score(&exact, &weights));
+    }
+
+    #[test]
+    fn weights_change_which_match_wins() {
+        let mut named = numbered(1);
+        named.name = "Blood".to_string();
+        let mut described = numbered(2);
+        described.description = "Blood".to_string();
+
+        let defaults = Weights::from_env(|_| None);
+        assert_eq!(defaults, Weights::default());
+        let score = |card: &Card, weights: &Weights| score_card(card, "blood", weights).unwrap();
+        assert!(score(&named, &defaults) > score(&described, &defaults));
+
+        let weights = Weights::from_env(|x| match x {
+            "WEIGHT_NAME" => Some(" 0.5".to_string()),
+            "WEIGHT_DESC" => Some("2".to_string()),
+            "WEIGHT_OTHER" => Some("-1".to_string()),
+            _ => None,
+        });
+        assert_eq!(weights.other, defaults.other);
+        assert!(score(&described, &weights) > score(&named, &weights));
+    }
+
+    #[test]
+    fn search_cache_keeps_the_matches() {
+        let mut cards: Vec<Card> = (0..500)
+            .map(|x| {
+                let mut card = numbered(x % 10);
+                card.id = format!("card_{x}");
+                card.name = format!("Card number {x}");
+                card.description = if x % 2 == 0 {
+                    "Deal damage to a creature, then heal as much.".to_string()
+                } else {
+                    "Draw a card.".to_string()
+                };
+                card
+            })
+            .collect();
+        let query = params("query=heal+creature").parse().unwrap();
+        let ids = |cards: &[Card]| -> Vec<String> {
+            search(&query, cards).iter().map(|x| x.id.clone()).collect()
+        };
+
+        let uncached = ids(&cards);
+        for card in &mut cards {
+            card.build_search_cache();
+        }
+        assert_eq!(uncached.len(), 250);
+        assert_eq!(ids(&cards), uncached);
+    }
+
+    #[test]
+    fn parallel_search_keeps_the_order() {
+        let cards: Vec<Card> = (0..5000)
+            .map(|x| {
+                let mut card = numbered(x % 10);
+                card.id = format!("card_{x}");
+                card.name = format!("Card number {x}");
+                card.description = format!("Deal {x} damage to a creature, then heal as much.");
+                card
+            })
+            .collect();
+        let query = params("query=creature+number&regex=heal%7Cdam.ge&cost=%3E2")
+            .parse()
+            .unwrap();
+        let serial: Vec<&Card> = cards.iter().filter(|x| query.matches(x)).collect();
+        let parallel = search(&query, &cards);
+        assert_eq!(serial.len(), 3500);
+        assert!(serial.len() > SEARCH_CHUNK);
+        assert_eq!(
+            serial.iter().map(|x| &x.id).collect::<Vec<_>>(),
+            parallel.iter().map(|x| &x.id).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn invalid_cost_is_an_error() {
+        assert!(params("cost=cheap").parse().is_err());
+        assert!(params("cost=2-").parse().is_err());
+        assert!(params("cost=%3E%3D").parse().is_err());
+    }
 }
@@ -1,6 +1,6 @@
-use crate::cards::Card;
-
-use super::{Comparison, Errors, QueryRestriction};
+use super::{
+    Comparison, Errors, ListProperty, NumberProperty, Query, QueryRestriction, TextProperty,
+};
 
 #[derive(Debug)]
 enum Token {
@@ -77,7 +77,12 @@ fn tokenize_query(q: &str) -> Result<Vec<Token>, Errors> {
     Ok(tokens)
 }
 
-pub fn query_parser(q: &str) -> Result<Vec<QueryRestriction>, Errors> {
+/// The params of `param:value` pairs, without their short forms.
+pub const QUERY_PARAMS: &[&str] = &[
+    "name", "type", "kin", "keyword", "set", "artist", "cost", "power", "health", "defense",
+];
+
+pub fn query_parser(q: &str) -> Result<Query, Errors> {
     let q = tokenize_query(q)?;
     let mut restrictions = vec![];
     let mut string = String::new();
@@ -90,57 +95,59 @@ pub fn query_parser(q: &str) -> Result<Vec<QueryRestriction>, Errors> {
             Token::Param(param, value) => match param.as_str() {
                 "cost" | "c" => {
                     let cmp = text_comparison_parser(value)?;
-                    restrictions.push(QueryRestriction::Comparison(Box::new(Card::get_cost), cmp));
+                    restrictions.push(QueryRestriction::Comparison(NumberProperty::Cost, cmp));
                 }
                 "health" | "h" | "hp" => {
                     let cmp = text_comparison_parser(value)?;
-                    restrictions.push(QueryRestriction::Comparison(
-                        Box::new(Card::get_health),
-                        cmp,
-                    ));
+                    restrictions.push(QueryRestriction::Comparison(NumberProperty::Health, cmp));
                 }
                 "power" | "strength" | "damage" | "p" | "dmg" | "str" => {
                     let cmp = text_comparison_parser(value)?;
-                    restrictions.push(QueryRestriction::Comparison(Box::new(Card::get_power), cmp));
+                    restrictions.push(QueryRestriction::Comparison(NumberProperty::Power, cmp));
                 }
                 "defense" | "def" | "d" => {
                     let cmp = text_comparison_parser(value)?;
-                    restrictions.push(QueryRestriction::Comparison(
-                        Box::new(Card::get_defense),
-                        cmp,
-                    ));
+                    restrictions.push(QueryRestriction::Comparison(NumberProperty::Defense, cmp));
                 }
                 "name" | "n" => restrictions.push(QueryRestriction::Contains(
-                    Box::new(Card::get_name),
+                    TextProperty::Name,
                     value.clone(),
                 )),
                 "type" | "t" => restrictions.push(QueryRestriction::Contains(
-                    Box::new(Card::get_type),
-                    value.clone(),
-                )),
-                "kin" | "k" => restrictions.push(QueryRestriction::Has(
-                    Box::new(Card::get_kins),
-                    value.clone(),
-                )),
-                "keyword" | "kw" => restrictions.push(QueryRestriction::HasKw(
-                    Box::new(Card::get_keywords),
+                    TextProperty::Type,
                     value.clone(),
                 )),
-                _ => return Err(Errors::UnknownParam),
-            },
-            Token::SuperParam(param, value) => {
-                if param == "devour" {
-                    todo!();
+                "kin" | "k" => {
+                    restrictions.push(QueryRestriction::Has(ListProperty::Kins, value.clone()));
                 }
-            }
+                "set" | "s" => {
+                    restrictions.push(QueryRestriction::Contains(TextProperty::Set, value.clone()));
+                }
+                "artist" | "a" => {
+                    restrictions.push(QueryRestriction::Has(ListProperty::Artists, value.clone()));
+                }
+                "keyword" | "kw" => match value.find(['<', '>', '=', '!']) {
+                    Some(split) => {
+                        let (name, comparison) = value.split_at(split);
+                        restrictions.push(QueryRestriction::KeywordValue(
+                            name.to_string(),
+                            text_comparison_parser(comparison)?,
+                        ));
+                    }
+                    None => restrictions.push(QueryRestriction::HasKw(value.clone())),
+                },
+                _ => return Err(Errors::UnknownParam(param.clone())),
+            },
+            // Nested queries like `devour:{...}` aren't supported yet.
+            Token::SuperParam(param, _) => return Err(Errors::UnknownParam(param.clone())),
         }
     }
     let string = string.trim().to_string();
     restrictions.push(QueryRestriction::Fuzzy(string));
-    Ok(restrictions)
+    Ok(Query { restrictions })
 }
 
-fn text_comparison_parser(s: &str) -> Result<Comparison, Errors> {
+pub fn text_comparison_parser(s: &str) -> Result<Comparison, Errors> {
     match s.parse::<usize>() {
         Ok(x) => Ok(Comparison::Equal(x)),
         Err(_) => {
@@ -168,6 +175,11 @@ fn text_comparison_parser(s: &str) -> Result<Comparison, Errors> {
                 end.parse::<usize>()
                     .map(Comparison::NotEqual)
                     .map_err(|_| Errors::InvalidComparisonString)
+            } else if let Some((start, end)) = s.split_once('-') {
+                match (start.parse::<usize>(), end.parse::<usize>()) {
+                    (Ok(start), Ok(end)) => Ok(Comparison::Between(start, end)),
+                    _ => Err(Errors::InvalidComparisonString),
+                }
             } else {
                 Err(Errors::InvalidComparisonString)
             }
@@ -0,0 +1,39 @@
+//! Cards for tests. Every test builds its cards from [`card_json`], so cards only differ in what
+//! the test is about.
+
+use serde_json::{json, Value};
+
+use crate::cards::Card;
+
+/// A creature with id `id`, named like it, that costs 1 and has 1 in every stat.
+pub fn card_json(id: &str) -> Value {
+    json!({
+        "id": id,
+        "name": id,
+        "description": "A test card",
+        "cost": 1,
+        "health": 1,
+        "defense": 1,
+        "power": 1,
+        "type": "creature",
+        "set": "Test",
+        "legality": {},
+    })
+}
+
+/// The card of [`card_json`], with `fields` replacing or adding to its fields.
+pub fn card_with(id: &str, fields: Value) -> Card {
+    let mut card = card_json(id);
+    if let (Some(card), Value::Object(fields)) = (card.as_object_mut(), fields) {
+        card.extend(fields);
+    }
+    serde_json::from_value(card).unwrap()
+}
+
+/// A card with id `card_{cost}`, named `Card {cost}`, that costs `cost`.
+pub fn numbered(cost: usize) -> Card {
+    card_with(
+        &format!("card_{cost}"),
+        json!({ "name": format!("Card {cost}"), "cost": cost }),
+    )
+}